@@ -0,0 +1,303 @@
+//! Connection to the window manager through the asynchronous `tokio-i3ipc`, chosen by `--async-ipc`
+//!
+//! Each request blocks on a single threaded runtime, so the insertion methods plan and check their
+//! commands like on the blocking connection. New windows are awaited on the event stream with a
+//! timeout, instead of in a listener thread which is left behind after it
+use std::{io, pin::Pin, time::Instant};
+
+use i3ipc::reply::{CommandOutcome, Workspace};
+use serde::{de::DeserializeOwned, Deserialize};
+use thiserror::Error;
+use tokio::runtime::{Builder, Runtime};
+use tokio_i3ipc::{
+    event::{Event, Subscribe, WindowChange},
+    msg::Msg,
+    reply::Success,
+    I3,
+};
+use tokio_stream::{Stream, StreamExt};
+
+use crate::{
+    backend::{BackendError, WmBackend},
+    connection::log_outcomes,
+    insertion::InsertMode,
+    raw_tree::{RawNode, Rect, VersionReply},
+    retry::RetryPolicy,
+};
+
+/// Failure of a request, telling whether its message was sent
+#[derive(Debug, Error)]
+enum RequestError {
+    #[error("Could not send request: {0}")]
+    Send(#[source] io::Error),
+    #[error("Could not receive reply: {0}")]
+    Receive(#[source] io::Error),
+}
+
+impl From<RequestError> for BackendError {
+    fn from(error: RequestError) -> Self {
+        match error {
+            RequestError::Send(error) | RequestError::Receive(error) => Self::Async(error),
+        }
+    }
+}
+
+/// Workspace as sent in the `GET_WORKSPACES` reply
+#[derive(Debug, Deserialize)]
+struct WorkspaceReply {
+    num: i32,
+    name: String,
+    visible: bool,
+    focused: bool,
+    urgent: bool,
+    rect: Rect,
+    output: String,
+}
+
+impl From<WorkspaceReply> for Workspace {
+    fn from(reply: WorkspaceReply) -> Self {
+        Self {
+            num: reply.num,
+            name: reply.name,
+            visible: reply.visible,
+            focused: reply.focused,
+            urgent: reply.urgent,
+            rect: (
+                reply.rect.x,
+                reply.rect.y,
+                reply.rect.width,
+                reply.rect.height,
+            ),
+            output: reply.output,
+        }
+    }
+}
+
+/// Build the single threaded runtime the requests block on
+fn runtime() -> io::Result<Runtime> {
+    Builder::new_current_thread().enable_all().build()
+}
+
+/// Connect on `runtime` following `retry`
+fn connect(runtime: &Runtime, retry: RetryPolicy) -> io::Result<I3> {
+    retry.retry(
+        "Connecting asynchronously",
+        |_| runtime.block_on(I3::connect()),
+        |_| true,
+    )
+}
+
+/// Asynchronous connection to the window manager at the socket given by `I3SOCK`
+pub struct AsyncIpc {
+    runtime: Runtime,
+    conn: I3,
+    /// Retrying of connecting and of requests failing on a lost connection
+    retry: RetryPolicy,
+}
+
+impl AsyncIpc {
+    /// Connect to the window manager, retrying following `retry`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the runtime can not be started, or the window manager can not be connected to
+    pub fn connect(retry: RetryPolicy) -> io::Result<Self> {
+        let runtime = runtime()?;
+        let conn = connect(&runtime, retry)?;
+        Ok(Self {
+            runtime,
+            conn,
+            retry,
+        })
+    }
+
+    /// Send `msg` with `payload` and read its reply, retrying on a new connection if it fails with a `transient` error
+    fn request<T: DeserializeOwned>(
+        &mut self,
+        what: &str,
+        msg: Msg,
+        payload: &str,
+        transient: impl Fn(&RequestError) -> bool,
+    ) -> Result<T, RequestError> {
+        log::debug!("{what}");
+        let Self {
+            runtime,
+            conn,
+            retry,
+        } = self;
+        retry.retry(
+            what,
+            |retrying| {
+                if retrying {
+                    match connect(runtime, RetryPolicy::default()) {
+                        Ok(new) => *conn = new,
+                        Err(error) => log::debug!("Could not reconnect: {error}"),
+                    }
+                }
+                runtime.block_on(async {
+                    conn.send_msg_body(msg, payload)
+                        .await
+                        .map_err(RequestError::Send)?;
+                    Ok(conn
+                        .read_msg::<T>()
+                        .await
+                        .map_err(RequestError::Receive)?
+                        .body)
+                })
+            },
+            transient,
+        )
+    }
+}
+
+impl WmBackend for AsyncIpc {
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>, BackendError> {
+        let workspaces = self.request::<Vec<WorkspaceReply>>(
+            "Requesting workspaces asynchronously",
+            Msg::Workspaces,
+            "",
+            |_| true,
+        )?;
+        Ok(workspaces.into_iter().map(Workspace::from).collect())
+    }
+
+    fn get_tree(&mut self) -> Result<RawNode, BackendError> {
+        Ok(self.request("Requesting tree asynchronously", Msg::Tree, "", |_| true)?)
+    }
+
+    fn get_mode(&mut self) -> Result<InsertMode, BackendError> {
+        let version = self.request::<VersionReply>(
+            "Requesting version asynchronously",
+            Msg::Version,
+            "",
+            |_| true,
+        )?;
+        Ok(InsertMode::from_variant(version.variant.as_deref()))
+    }
+
+    /// A command which was sent is not retried, even if its reply was lost, as it might have run already
+    fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, BackendError> {
+        log::info!("Running command asynchronously: {command}");
+        let outcomes = self
+            .request::<Vec<Success>>(
+                "Sending command asynchronously",
+                Msg::RunCommand,
+                command,
+                |error| matches!(error, RequestError::Send(_)),
+            )?
+            .into_iter()
+            .map(|x| CommandOutcome {
+                success: x.success,
+                error: x.error,
+            })
+            .collect::<Vec<_>>();
+        log_outcomes(&outcomes);
+        Ok(outcomes)
+    }
+}
+
+/// Window events read asynchronously, from the moment of the subscription on
+pub struct WindowEvents {
+    runtime: Runtime,
+    events: Pin<Box<dyn Stream<Item = io::Result<Event>>>>,
+    /// Retrying of resubscribing after the event connection was lost
+    retry: RetryPolicy,
+}
+
+impl WindowEvents {
+    /// Subscribe to window events, following `retry`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the runtime can not be started, or the subscription fails
+    pub fn subscribe(retry: RetryPolicy) -> io::Result<Self> {
+        let runtime = runtime()?;
+        let events = Self::events(&runtime, retry)?;
+        Ok(Self {
+            runtime,
+            events,
+            retry,
+        })
+    }
+
+    /// Connect and subscribe to window events on `runtime`, following `retry`
+    fn events(
+        runtime: &Runtime,
+        retry: RetryPolicy,
+    ) -> io::Result<Pin<Box<dyn Stream<Item = io::Result<Event>>>>> {
+        retry.retry(
+            "Subscribing to window events asynchronously",
+            |_| {
+                runtime.block_on(async {
+                    let mut conn = I3::connect().await?;
+                    conn.subscribe([Subscribe::Window]).await?;
+                    Ok(Box::pin(conn.listen()) as Pin<Box<dyn Stream<Item = _>>>)
+                })
+            },
+            |_| true,
+        )
+    }
+
+    /// Wait until `deadline` for the container of the next new window
+    ///
+    /// A lost event connection is replaced by a new subscription, missing the windows in between.
+    /// Returns `None` once the deadline passed, or if the events can not be resubscribed to
+    pub fn next(&mut self, deadline: Instant) -> Option<i64> {
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            let event = self.runtime.block_on(async {
+                tokio::time::timeout(remaining, self.events.next())
+                    .await
+                    .ok()
+            })?;
+            match event {
+                Some(Ok(Event::Window(data))) if data.change == WindowChange::New => {
+                    return i64::try_from(data.container.id).ok();
+                }
+                Some(Ok(_)) => {}
+                lost => {
+                    if let Some(Err(error)) = lost {
+                        log::debug!("Lost window events: {error}");
+                    }
+                    match Self::events(&self.runtime, self.retry) {
+                        Ok(events) => self.events = events,
+                        Err(error) => {
+                            log::warn!("Could not resubscribe to window events: {error}");
+                            return None;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn workspace_reply() -> Result<(), serde_json::Error> {
+        let reply = r#"[{"id": 1, "num": 2, "name": "2: web", "visible": true, "focused": false,
+            "urgent": false, "rect": {"x": 0, "y": 20, "width": 800, "height": 580},
+            "output": "DP-1"}]"#;
+        let workspaces = serde_json::from_str::<Vec<WorkspaceReply>>(reply)?
+            .into_iter()
+            .map(Workspace::from)
+            .collect::<Vec<_>>();
+        let [workspace] = workspaces.as_slice() else {
+            panic!("expected one workspace, got {}", workspaces.len());
+        };
+        assert_eq!(
+            (
+                workspace.num,
+                workspace.name.as_str(),
+                workspace.output.as_str()
+            ),
+            (2, "2: web", "DP-1")
+        );
+        assert_eq!(workspace.rect, (0, 20, 800, 580));
+        assert!(workspace.visible && !workspace.focused && !workspace.urgent);
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+//! Connections to the window manager the insertion methods work with
+use i3ipc::reply::{CommandOutcome, Workspace};
+use thiserror::Error;
+
+#[cfg(feature = "swayipc")]
+use crate::sway::{SwayConnection, SwayError};
+use crate::{
+    connection::Connection,
+    insertion::InsertMode,
+    raw_tree::{self, RawNode, RawTreeError},
+    util::get_workspaces,
+};
+
+/// Errors of the window manager connections
+#[derive(Debug, Error)]
+pub enum BackendError {
+    #[error("i3 IPC connection error: \"{0}\"")]
+    I3(#[from] i3ipc::MessageError),
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[cfg(feature = "swayipc")]
+    #[error("{0}")]
+    Sway(#[from] SwayError),
+    #[cfg(feature = "async")]
+    #[error("Async IPC error: {0}")]
+    Async(#[source] std::io::Error),
+}
+
+/// Connection to the window manager, querying its state and running commands
+///
+/// The insertion methods build their commands from the queried state, independent of the IPC library
+pub trait WmBackend {
+    /// Get the workspaces, in the order of their outputs
+    ///
+    /// # Errors
+    ///
+    /// Fails if the window manager can not be queried
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>, BackendError>;
+
+    /// Get the container tree, with the properties `i3ipc` does not expose
+    ///
+    /// # Errors
+    ///
+    /// Fails if the window manager can not be queried
+    fn get_tree(&mut self) -> Result<RawNode, BackendError>;
+
+    /// Get the insertion method suited for the window manager, told by its version reply
+    ///
+    /// # Errors
+    ///
+    /// Fails if the window manager can not be queried
+    fn get_mode(&mut self) -> Result<InsertMode, BackendError>;
+
+    /// Send `command`, returning the outcome of each command in it
+    ///
+    /// # Errors
+    ///
+    /// Fails if the command can not be sent, not if it fails to run
+    fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, BackendError>;
+
+    /// Send `commands` in a single message, returning the outcome of each of them
+    ///
+    /// # Errors
+    ///
+    /// Fails if the commands can not be sent, not if they fail to run
+    fn run_commands(&mut self, commands: &[String]) -> Result<Vec<CommandOutcome>, BackendError> {
+        self.run_command(&commands.join("; "))
+    }
+}
+
+impl WmBackend for Connection {
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>, BackendError> {
+        Ok(get_workspaces(self)?)
+    }
+
+    /// Requested on a separate connection to the same socket, as `i3ipc` drops most properties of the tree
+    fn get_tree(&mut self) -> Result<RawNode, BackendError> {
+        Ok(raw_tree::get_tree(self)?)
+    }
+
+    /// Requested on a separate connection to the same socket, as `i3ipc` drops the variant of the version
+    fn get_mode(&mut self) -> Result<InsertMode, BackendError> {
+        let variant = raw_tree::get_variant(self.retry_policy())?;
+        Ok(InsertMode::from_variant(variant.as_deref()))
+    }
+
+    fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, BackendError> {
+        Ok(Self::run_command(self, command)?)
+    }
+}
+
+#[cfg(feature = "swayipc")]
+impl WmBackend for SwayConnection {
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>, BackendError> {
+        Ok(Self::get_workspaces(self)?)
+    }
+
+    fn get_tree(&mut self) -> Result<RawNode, BackendError> {
+        Ok(Self::get_tree(self)?)
+    }
+
+    fn get_mode(&mut self) -> Result<InsertMode, BackendError> {
+        Ok(InsertMode::Sway)
+    }
+
+    fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, BackendError> {
+        Ok(Self::run_command(self, command)?)
+    }
+}
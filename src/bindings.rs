@@ -0,0 +1,55 @@
+//! Generate key bindings for the window manager configuration
+use std::io::Write;
+
+use crate::insertion::InsertMode;
+
+/// Options of the `gen-bindings` subcommand
+#[derive(clap::Args, Debug)]
+pub struct GenBindingsArgs {
+    /// Modifier of the bindings, like `Mod4` or `$mod`
+    #[clap(long, default_value = "$mod")]
+    modifier: String,
+
+    /// Key of the bindings, combined with Shift and Ctrl for the variants
+    #[clap(long, default_value = "i")]
+    key: String,
+}
+
+impl GenBindingsArgs {
+    /// Print `bindsym` lines to paste into the configuration of the window manager
+    ///
+    /// The bindings insert after and before the focused workspace, and insert after it moving the focused container
+    pub fn run(&self, bin_name: &str, mode: &InsertMode) -> std::io::Result<()> {
+        let (exec, mode_option) = match mode {
+            InsertMode::I3 => ("exec --no-startup-id", ""),
+            InsertMode::Sway => ("exec", " --mode sway"),
+        };
+        let bindings = [
+            (
+                "Insert a new workspace after the focused one",
+                "",
+                "",
+            ),
+            (
+                "Insert a new workspace before the focused one",
+                "Shift+",
+                " --before",
+            ),
+            (
+                "Insert a new workspace after the focused one, and move the focused container to it",
+                "Ctrl+",
+                " --container focused",
+            ),
+        ];
+        let mut stdout = std::io::stdout().lock();
+        for (description, extra_modifier, options) in bindings {
+            writeln!(stdout, "# {description}")?;
+            writeln!(
+                stdout,
+                "bindsym {}+{extra_modifier}{} {exec} {bin_name}{mode_option}{options}",
+                self.modifier, self.key
+            )?;
+        }
+        Ok(())
+    }
+}
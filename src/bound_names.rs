@@ -0,0 +1,57 @@
+//! Find workspace names the user can reach by key bindings of the i3 or sway config
+/// Arguments of the `workspace` command which are keywords instead of names
+const WORKSPACE_KEYWORDS: &[&str] = &[
+    "next",
+    "prev",
+    "next_on_output",
+    "prev_on_output",
+    "back_and_forth",
+];
+
+/// Replace the variables defined by `set $name value` in `line`
+///
+/// Longer variable names are replaced first, so `$ws1` does not clobber `$ws10`
+fn substitute_variables(line: &str, variables: &[(String, String)]) -> String {
+    variables
+        .iter()
+        .fold(line.to_owned(), |line, (name, value)| {
+            line.replace(name, value)
+        })
+}
+
+/// Get the workspace name the command `command` switches to or moves a container to
+fn workspace_argument(command: &str) -> Option<String> {
+    let mut words = command.split_whitespace();
+    words.find(|x| *x == "workspace")?;
+    let name = words
+        .skip_while(|x| *x == "number" || x.starts_with("--"))
+        .collect::<Vec<_>>()
+        .join(" ");
+    let name = name.trim_matches('"');
+    (!name.is_empty() && !WORKSPACE_KEYWORDS.contains(&name)).then(|| name.to_owned())
+}
+
+/// Get all workspace names used by `bindsym` and `bindcode` lines of the config file `config`
+pub fn bound_workspace_names(config: &str) -> Vec<String> {
+    // Sorted by descending name length, see `substitute_variables`
+    let mut variables: Vec<(String, String)> = Vec::new();
+    let mut names = Vec::new();
+    for line in config.lines().map(str::trim) {
+        let mut words = line.split_whitespace();
+        match words.next() {
+            Some("set") => {
+                if let (Some(name), value) = (words.next(), words.collect::<Vec<_>>().join(" ")) {
+                    variables.retain(|(x, _)| x != name);
+                    variables.push((name.to_owned(), value.trim_matches('"').to_owned()));
+                    variables.sort_by_key(|(name, _)| std::cmp::Reverse(name.len()));
+                }
+            }
+            Some("bindsym" | "bindcode") => {
+                let line = substitute_variables(line, &variables);
+                names.extend(line.split([';', ',']).filter_map(workspace_argument));
+            }
+            _ => (),
+        }
+    }
+    names
+}
@@ -0,0 +1,793 @@
+//! Command line interface of the `i3-insert-workspace` binary
+use std::{path::PathBuf, process::ExitCode};
+
+use clap::{CommandFactory, Parser};
+use thiserror::Error;
+
+#[cfg(feature = "async")]
+use crate::backend::BackendError;
+use crate::{
+    bindings::GenBindingsArgs,
+    completions::{self, CompletionsArgs},
+    config::{Config, ConfigError},
+    connection::Connection,
+    container::{ContainerError, ContainerSelection, SwallowArgs},
+    criteria::{self, CriteriaError},
+    duplicate::{DuplicateArgs, DuplicateError},
+    insert_workspace_rename, insert_workspace_swap,
+    insertion::{
+        run_insertion, FloatPlacement, FollowSelection, InsertMode, InsertionError, Layout,
+        ModeSelection,
+    },
+    interactive::{InteractiveArgs, InteractiveError},
+    logging::VerbosityArgs,
+    naming::{NameSelection, NamingContext, NamingError},
+    numbered::{CompactArgs, NumberedError, NumberedSelection, RenumberArgs},
+    pivot::{self, BoundaryOutput, PivotError, PivotSelection, PIVOT_NAME},
+    query::{FocusedArgs, ListArgs, OutputsArgs, QueryError, WhereArgs},
+    raw_tree::{self, RawTreeError},
+    remove::{RemoveArgs, RemoveError},
+    reorder::{
+        self, ArchiveArgs, BubbleArgs, MoveArgs, ReorderError, ReverseArgs, RotateArgs, SortArgs,
+        SwapArgs,
+    },
+    report::{ReportArgs, ReportError},
+    retry::RetryArgs,
+    rofi::{RofiError, RofiModeArgs},
+    util::{
+        self, get_workspaces, run_commands, CommandError, ContainerChanges, InsertionDestination,
+        Pivot, WorkspaceRename, SCRATCHPAD_OUTPUT,
+    },
+};
+
+/// Simple program to insert a named workspace before or after another workspace
+#[derive(clap::Parser, Debug)]
+#[clap(version, args_conflicts_with_subcommands = true)]
+struct Args {
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    #[clap(flatten)]
+    insert: InsertArgs,
+
+    /// Method to insert workspace is handled differently for i3 and sway.
+    ///
+    /// `auto` detects the window manager by `SWAYSOCK`, `I3SOCK` or its version reply.
+    /// Defaults to the mode of the config file, or `auto`
+    #[clap(short, long, env = "I3IW_MODE", value_enum, global = true)]
+    mode: Option<ModeSelection>,
+
+    /// Print the i3 commands instead of running them.
+    ///
+    /// The window manager is still queried to plan the commands, each message is printed on its own line
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    /// IPC socket of the window manager, overriding `I3SOCK` and `SWAYSOCK`.
+    ///
+    /// Without it `I3SOCK` is used, then `SWAYSOCK`, then the path given by `i3 --get-socketpath`
+    #[clap(long, value_name = "PATH", global = true)]
+    socket: Option<PathBuf>,
+
+    /// Insert and wait for `--swallow` windows through the asynchronous `tokio-i3ipc`.
+    ///
+    /// New windows are read from the event stream with a timeout, instead of in a listener thread
+    #[cfg(feature = "async")]
+    #[clap(long, env = "I3IW_ASYNC_IPC", global = true)]
+    async_ipc: bool,
+
+    #[clap(flatten)]
+    retry: RetryArgs,
+
+    #[clap(flatten)]
+    verbosity: VerbosityArgs,
+}
+
+/// Operations on workspaces
+///
+/// Without a subcommand, the options of `insert` are accepted directly
+#[derive(clap::Subcommand, Debug)]
+enum Command {
+    /// Insert a new workspace before or after a pivot workspace, the default without a subcommand
+    Insert(Box<InsertArgs>),
+    /// Number the numbered workspaces densely from 1, in their current order.
+    ///
+    /// Labels of `number: label` names are kept
+    Renumber(RenumberArgs),
+    /// Close the gaps between workspace numbers, keeping their order.
+    ///
+    /// For example the numbers 1, 2, 5, 7 become 1, 2, 3, 4
+    Compact(CompactArgs),
+    /// Sort the workspaces of an output
+    Sort(SortArgs),
+    /// Exchange the positions of two workspaces
+    Swap(SwapArgs),
+    /// Move an existing workspace, including its contents, before or after a pivot workspace
+    Move(MoveArgs),
+    /// Cyclically shift the workspaces of an output
+    Rotate(RotateArgs),
+    /// Reverse the order of the workspaces of an output
+    Reverse(ReverseArgs),
+    /// Remove a workspace, moving its windows to another workspace or killing them
+    Remove(RemoveArgs),
+    /// Swap the focused workspace with its neighbor on the same output
+    Bubble(BubbleArgs),
+    /// Insert a new workspace after the focused one, and move the focused container or matching windows to it
+    Split(SplitArgs),
+    /// Insert a new workspace, and move all windows matching criteria to it
+    Gather(GatherArgs),
+    /// Move a workspace to the last position of its output, or of an archive output
+    Archive(ArchiveArgs),
+    /// Print a shell completion script, completing pivots with the current workspace names
+    Completions(CompletionsArgs),
+    /// Print the names of all workspaces, used by the completion scripts
+    #[clap(name = completions::WORKSPACES_SUBCOMMAND, hide = true)]
+    CompleteWorkspaces,
+    /// List the workspaces with their output, position, number, and whether they are visible or focused.
+    ///
+    /// Each line holds the output, index, number, flags and name, separated by tabs
+    List(ListArgs),
+    /// Print the focused output, container id and workspace, separated by tabs
+    Focused(FocusedArgs),
+    /// List the active outputs, each followed by its workspaces in order with the visible one marked by `*`
+    Outputs(OutputsArgs),
+    /// Print the output and position of a workspace, separated by a tab.
+    ///
+    /// Fails if the workspace does not exist
+    Where(WhereArgs),
+    /// Print key bindings for the i3 or sway configuration, for common ways to insert workspaces
+    GenBindings(GenBindingsArgs),
+    /// Print the man page in roff format, for packagers
+    #[clap(hide = true)]
+    GenerateManpage,
+    /// Insert a new workspace after a workspace, with the same layout of containers.
+    ///
+    /// Windows are replaced by placeholders, which swallow new windows of the same class.
+    /// Only supported by i3
+    Duplicate(DuplicateArgs),
+    /// Choose where to insert a new workspace and its name on the terminal.
+    ///
+    /// Lists the workspaces of each output with numbered positions between them
+    Interactive(InteractiveArgs),
+    /// Script mode for rofi, listing positions next to each workspace and inserting at the selected one.
+    ///
+    /// Used as `rofi -show insert -modi "insert:i3-insert-workspace rofi-mode"`
+    RofiMode(RofiModeArgs),
+}
+
+/// Options of the `split` subcommand
+#[derive(clap::Args, Debug)]
+struct SplitArgs {
+    /// Criteria selecting the moved windows, like `[class="Slack"]`, instead of the focused container
+    #[clap(long)]
+    criteria: Option<String>,
+
+    #[clap(flatten)]
+    name: NameSelection,
+}
+
+/// Options of the `gather` subcommand
+#[derive(clap::Args, Debug)]
+struct GatherArgs {
+    /// Criteria selecting the moved windows on all workspaces, like `[class="Slack"]`
+    #[clap(long)]
+    criteria: String,
+
+    #[clap(flatten)]
+    pivot: PivotSelection,
+
+    /// Insert before the pivot instead of after it.
+    #[clap(short, long)]
+    before: bool,
+
+    #[clap(flatten)]
+    name: NameSelection,
+}
+
+/// Options for inserting a new workspace
+#[derive(clap::Args, Debug)]
+struct InsertArgs {
+    /// Name of the new workspace, like `--name`
+    #[clap(
+        value_name = "NAME",
+        conflicts_with_all = ["name", "name_prompt", "name_from", "name_template", "name_scheme", "count"]
+    )]
+    positional_name: Option<String>,
+
+    /// Workspace before or after which the new workspace is inserted, like `--pivot`
+    #[clap(
+        value_name = "PIVOT",
+        group = PIVOT_NAME,
+        conflicts_with_all = ["pivot", "pivot_num", "pivot_regex", "pivot_output", "pivot_mark"]
+    )]
+    positional_pivot: Option<String>,
+
+    #[clap(flatten)]
+    pivot: PivotSelection,
+
+    /// Insert before the pivot instead of after it.
+    ///
+    /// `--before X` is short for `--pivot X --before`. A following positional name is taken as the pivot.
+    /// Not read from the environment, as a value there would be taken as the pivot
+    #[clap(
+        short,
+        long,
+        num_args = 0..=1,
+        default_missing_value = "",
+        value_name = "PIVOT",
+        group = PIVOT_NAME,
+        conflicts_with = "positional_pivot"
+    )]
+    before: Option<String>,
+
+    /// Insert after this workspace, short for `--pivot X`
+    #[clap(
+        long, env = "I3IW_AFTER",
+        value_name = "PIVOT",
+        group = PIVOT_NAME,
+        conflicts_with_all = ["before", "positional_pivot", "pivot", "pivot_num", "pivot_regex", "pivot_output", "pivot_mark"]
+    )]
+    after: Option<String>,
+
+    /// Treat the workspaces of all outputs as one sequence.
+    ///
+    /// Inserting after the last workspace of an output is the same position as inserting
+    /// before the first workspace of the next output, this selects which output gets the new workspace
+    #[clap(long, env = "I3IW_GLOBAL_ORDER", value_enum)]
+    global_order: Option<BoundaryOutput>,
+
+    #[clap(flatten)]
+    name: NameSelection,
+
+    #[clap(flatten)]
+    numbering: NumberedSelection,
+
+    #[clap(flatten)]
+    containers: ContainerSelection,
+
+    #[clap(flatten)]
+    follow: FollowSelection,
+
+    #[clap(flatten)]
+    swallow: SwallowArgs,
+
+    /// Mark the first moved container, so scripts can address it afterwards
+    #[clap(long, env = "I3IW_MARK")]
+    mark: Option<String>,
+
+    /// Layout of the new workspace, instead of the default layout
+    #[clap(long, env = "I3IW_LAYOUT", value_enum)]
+    layout: Option<Layout>,
+
+    /// Placement of moved floating containers on the output of the new workspace
+    #[clap(long, env = "I3IW_FLOAT_PLACEMENT", value_enum, default_value_t = FloatPlacement::Keep)]
+    float_placement: FloatPlacement,
+
+    /// Only insert the workspace if no workspace with its name exists.
+    ///
+    /// An existing workspace is moved to the position of the new workspace instead, and focused
+    #[clap(long, env = "I3IW_IF_ABSENT", conflicts_with = "count")]
+    if_absent: bool,
+
+    /// Number of workspaces inserted after each other.
+    ///
+    /// A name is chosen for each of them, so templates and name schemes should yield distinct names.
+    /// Empty workspaces are removed by the window manager once they lose focus,
+    /// so all but the last new workspace only persist if windows are moved to them
+    #[clap(
+        long, env = "I3IW_COUNT",
+        default_value_t = 1,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..),
+        conflicts_with_all = ["name", "name_prompt", "name_from"]
+    )]
+    count: usize,
+
+    #[clap(flatten)]
+    report: ReportArgs,
+}
+
+/// The location of a container, given by the output and workspace that contains it
+struct I3ConLocation {
+    output: String,
+    workspace: String,
+    container: i64,
+}
+
+#[derive(Debug, Error)]
+enum FocusError {
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Focus chain in tree incorrect")]
+    BrokenFocusChain,
+    #[error("Focus entry incorrect")]
+    IncorrectFocusEntry,
+    #[error("Focused output unnamed")]
+    UnnamedOutput,
+    #[error("Focused workspace unnamed")]
+    UnnamedWorkspace,
+    #[error("Focus is on the hidden scratchpad")]
+    ScratchpadFocused,
+    #[error("No focused output found")]
+    OutputNameNotFound,
+    #[error("No focused workspace found")]
+    WorkspaceNameNotFound,
+}
+
+/// Get the currently focused output, workspace and container
+fn focused(conn: &mut Connection) -> Result<I3ConLocation, FocusError> {
+    let t = raw_tree::get_tree(conn)?;
+
+    let mut current = &t;
+    let mut output = None;
+    let mut workspace = None;
+    while !current.focused {
+        let next_focus_item = *current.focus.first().ok_or(FocusError::BrokenFocusChain)?;
+        current = current
+            .nodes
+            .iter()
+            .chain(current.floating_nodes.iter())
+            .find(|x| x.id == next_focus_item)
+            .ok_or(FocusError::IncorrectFocusEntry)?;
+
+        match current.nodetype.as_str() {
+            "output" => {
+                let name = current.name.as_ref().ok_or(FocusError::UnnamedOutput)?;
+                if name == SCRATCHPAD_OUTPUT {
+                    return Err(FocusError::ScratchpadFocused);
+                }
+                output = Some(name);
+            }
+            "workspace" => {
+                workspace = Some(current.name.as_ref().ok_or(FocusError::UnnamedWorkspace)?);
+            }
+            _ => (),
+        }
+    }
+    Ok(I3ConLocation {
+        output: output.ok_or(FocusError::OutputNameNotFound)?.clone(),
+        workspace: workspace.ok_or(FocusError::WorkspaceNameNotFound)?.clone(),
+        container: current.id,
+    })
+}
+
+#[derive(Debug, Error)]
+enum MainError {
+    #[error("{0}")]
+    Insertion(#[from] InsertionError),
+    #[error("Could not connect to i3 IPC: {0}")]
+    Connection(
+        #[from]
+        #[source]
+        i3ipc::EstablishError,
+    ),
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Container tree error: {0}")]
+    TreeError(#[from] FocusError),
+    #[error("Numbering error: {0}")]
+    Numbered(#[from] NumberedError),
+    #[error("Pivot error: {0}")]
+    Pivot(#[from] PivotError),
+    #[error("Could not name workspace: {0}")]
+    Naming(#[from] NamingError),
+    #[error("Reordering error: {0}")]
+    Reorder(#[from] ReorderError),
+    #[error("Removal error: {0}")]
+    Remove(#[from] RemoveError),
+    #[error("Duplication error: {0}")]
+    Duplicate(#[from] DuplicateError),
+    #[error("Command error: {0}")]
+    Command(#[from] CommandError),
+    #[error("Criteria error: {0}")]
+    Criteria(#[from] CriteriaError),
+    #[error("Could not write completions: {0}")]
+    Completions(#[from] std::io::Error),
+    #[error("Could not write bindings: {0}")]
+    Bindings(#[source] std::io::Error),
+    #[error("Could not write man page: {0}")]
+    Manpage(#[source] std::io::Error),
+    #[error("Configuration error: {0}")]
+    Config(#[from] ConfigError),
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Container selection error: {0}")]
+    Container(#[from] ContainerError),
+    #[error("Interactive selection failed: {0}")]
+    Interactive(#[from] InteractiveError),
+    #[error("rofi script mode failed: {0}")]
+    Rofi(#[from] RofiError),
+    #[error("Query failed: {0}")]
+    Query(#[from] QueryError),
+    #[error("Could not print result: {0}")]
+    Report(#[from] ReportError),
+}
+
+fn handle() -> Result<(), MainError> {
+    let args = Args::parse();
+    args.verbosity.init();
+
+    if let Some(Command::Completions(completions)) = &args.command {
+        return Ok(completions.run(Args::command())?);
+    }
+    if let Some(Command::GenerateManpage) = &args.command {
+        return clap_mangen::Man::new(Args::command())
+            .render(&mut std::io::stdout())
+            .map_err(MainError::Manpage);
+    }
+
+    let report_commands = match &args.command {
+        Some(Command::Insert(insert_args)) => insert_args.report.needs_commands(),
+        None => args.insert.report.needs_commands(),
+        Some(_) => false,
+    };
+    let print_commands = args.dry_run && !report_commands;
+
+    let config = Config::load()?;
+    util::select_socket(args.socket.as_deref());
+    let mode = args
+        .mode
+        .or(config.mode)
+        .unwrap_or(ModeSelection::Auto)
+        .resolve(args.socket.is_some(), args.retry.policy());
+
+    if let Some(Command::GenBindings(gen_bindings)) = &args.command {
+        return gen_bindings
+            .run(Args::command().get_name(), &mode)
+            .map_err(MainError::Bindings);
+    }
+
+    let mut conn = Connection::connect(args.retry.policy())?;
+    conn.set_dry_run(args.dry_run);
+    #[cfg(feature = "async")]
+    conn.set_async_ipc(args.async_ipc);
+    if report_commands {
+        conn.record_commands();
+    }
+
+    let result = run(&mut conn, &config, &mode, args.command, args.insert);
+    if print_commands {
+        print_recorded_commands(&conn);
+    }
+    result
+}
+
+/// Run `command` with the connection to the window manager, or insert a workspace as given by `insert_args`
+fn run(
+    conn: &mut Connection,
+    config: &Config,
+    mode: &InsertMode,
+    command: Option<Command>,
+    insert_args: InsertArgs,
+) -> Result<(), MainError> {
+    // These do not depend on the focus, so they also work while a scratchpad window is focused
+    let command = match command {
+        Some(Command::Compact(compact)) => return Ok(compact.run(conn)?),
+        Some(Command::Interactive(interactive)) => {
+            let (pivot, before, name) = interactive.run(conn)?;
+            return insert_containers(conn, config, mode, pivot, before, name, &[]);
+        }
+        Some(Command::List(list)) => return Ok(list.run(conn)?),
+        Some(Command::Outputs(outputs)) => return Ok(outputs.run(conn)?),
+        Some(Command::Where(location)) => return Ok(location.run(conn)?),
+        Some(Command::CompleteWorkspaces) => return Ok(completions::print_workspaces(conn)?),
+        command => command,
+    };
+
+    let focus = focused(conn)?;
+    match command {
+        Some(Command::Renumber(renumber)) => Ok(renumber.run(conn, &focus.output)?),
+        Some(Command::Sort(sort)) => Ok(sort.run(conn, mode, &focus.output)?),
+        Some(Command::Swap(swap)) => Ok(swap.run(conn, mode, &focus.workspace)?),
+        Some(Command::Move(relocation)) => Ok(relocation.run(conn, mode, &focus.workspace)?),
+        Some(Command::Rotate(rotate)) => Ok(rotate.run(conn, mode, &focus.output)?),
+        Some(Command::Reverse(reverse)) => Ok(reverse.run(conn, mode, &focus.output)?),
+        Some(Command::Remove(remove)) => Ok(remove.run(conn, &focus.workspace)?),
+        Some(Command::Bubble(bubble)) => Ok(bubble.run(conn, mode, &focus.workspace)?),
+        Some(Command::Split(split)) => {
+            let containers = match split.criteria {
+                Some(criteria) => criteria::matching_containers(conn, &criteria)?,
+                None => vec![focus.container],
+            };
+            let pivot = Pivot {
+                name: focus.workspace,
+                output: Some(focus.output),
+            };
+            insert_containers(conn, config, mode, pivot, false, split.name, &containers)
+        }
+        Some(Command::Gather(gather)) => {
+            let containers = criteria::matching_containers(conn, &gather.criteria)?;
+            let pivot = gather.pivot.resolve(conn, focus.workspace)?;
+            insert_containers(
+                conn,
+                config,
+                mode,
+                pivot,
+                gather.before,
+                gather.name,
+                &containers,
+            )
+        }
+        Some(Command::Archive(archive)) => Ok(archive.run(conn, mode, &focus.workspace)?),
+        Some(Command::Duplicate(duplicate)) => {
+            Ok(duplicate.run(conn, config, mode, &focus.workspace)?)
+        }
+        Some(Command::RofiMode(rofi_mode)) => match rofi_mode.run(conn, &focus.workspace)? {
+            Some((pivot, before, name)) => {
+                insert_containers(conn, config, mode, pivot, before, name, &[])
+            }
+            None => Ok(()),
+        },
+        // Handled before connecting, these are generated without a running window manager
+        Some(Command::Completions(_) | Command::GenerateManpage | Command::GenBindings(_)) => {
+            Ok(())
+        }
+        // Handled before resolving the focus
+        Some(
+            Command::Compact(_)
+            | Command::Interactive(_)
+            | Command::List(_)
+            | Command::Outputs(_)
+            | Command::Where(_)
+            | Command::CompleteWorkspaces,
+        ) => Ok(()),
+        Some(Command::Focused(focused)) => {
+            Ok(focused.run(&focus.output, &focus.workspace, focus.container)?)
+        }
+        Some(Command::Insert(insert_args)) => insert(conn, config, &focus, *insert_args, mode),
+        None => insert(conn, config, &focus, insert_args, mode),
+    }
+}
+
+/// Insert a new workspace next to `pivot` named by `name`, and move `containers` to it
+fn insert_containers(
+    conn: &mut Connection,
+    config: &Config,
+    mode: &InsertMode,
+    pivot: Pivot,
+    before: bool,
+    name: NameSelection,
+    containers: &[i64],
+) -> Result<(), MainError> {
+    let context = NamingContext {
+        pivot: &pivot,
+        before,
+        container: containers.first().copied(),
+        inserted: &[],
+    };
+    let name = name.resolve(conn, config, &context)?;
+    let destination = InsertionDestination::new(pivot, before);
+    run_insertion(
+        conn,
+        mode,
+        &destination,
+        &[name],
+        containers,
+        ContainerChanges::default(),
+        &[],
+    )?;
+    Ok(())
+}
+
+/// Insert a new workspace as given by `args`
+fn insert(
+    conn: &mut Connection,
+    config: &Config,
+    focus: &I3ConLocation,
+    mut args: InsertArgs,
+    mode: &InsertMode,
+) -> Result<(), MainError> {
+    args.follow.default_to(config.follow);
+
+    if let Some(name) = args.positional_name {
+        args.name.set_name(name);
+    }
+    let explicit_pivot = args
+        .positional_pivot
+        .or(args.after)
+        .or_else(|| args.before.clone().filter(|x| !x.is_empty()));
+    if let Some(pivot) = explicit_pivot {
+        args.pivot.set_pivot(pivot);
+    }
+    let before = args.before.is_some();
+
+    let pivot = args.pivot.resolve(conn, focus.workspace.clone())?;
+    let (pivot, before) = match args.global_order.or(config.global_order) {
+        Some(boundary_output) => pivot::cross_boundary(conn, pivot, before, boundary_output)?,
+        None => (pivot, before),
+    };
+
+    let (containers, sticky) = args.containers.resolve(conn, focus.container)?;
+    let changes = ContainerChanges {
+        mark: args.mark.as_deref(),
+        unstick: &sticky,
+    };
+
+    let mut names = Vec::new();
+    while names.len() < args.count {
+        let context = NamingContext {
+            pivot: &pivot,
+            before,
+            // The containers are only moved to the first new workspace
+            container: containers.first().copied().filter(|_| names.is_empty()),
+            inserted: &names,
+        };
+        let name = args.name.clone().resolve(conn, config, &context)?;
+        names.push(name);
+    }
+    let (names, renames) =
+        args.numbering
+            .resolve(conn, &pivot, before, args.name.numbering(), names)?;
+    log_plan(&pivot, before, &names, &containers, &renames);
+
+    if args.if_absent {
+        let workspaces = get_workspaces(conn)?;
+        if let Some(existing) = names
+            .first()
+            .filter(|name| workspaces.iter().any(|x| &&x.name == name))
+        {
+            reorder::move_workspace(conn, mode, existing, &pivot, before, &focus.workspace)?;
+            let mut commands = if containers.is_empty() {
+                vec![format!("workspace \"{existing}\"")]
+            } else {
+                changes
+                    .commands(&containers)
+                    .into_iter()
+                    .chain(containers.iter().map(|container_id| {
+                        format!(
+                            "[con_id={container_id}] move container to workspace \"{existing}\""
+                        )
+                    }))
+                    .collect()
+            };
+            commands.extend(
+                args.follow
+                    .commands(&focus.workspace, &[], existing, &containers),
+            );
+            run_commands(conn, &commands)?;
+            args.report.print(conn, &names, &containers, None)?;
+            return Ok(());
+        }
+    }
+
+    let destination = InsertionDestination::new(pivot, before);
+    // Subscribe before the insertion, to not miss windows of applications started right after it
+    let new_windows = args.swallow.subscribe(conn)?;
+    let plan = run_insertion(
+        conn,
+        mode,
+        &destination,
+        &names,
+        &containers,
+        changes,
+        &renames,
+    )?;
+    if let Some(name) = names.first() {
+        let mut commands = args
+            .layout
+            .map(|layout| layout.commands(name, &containers))
+            .unwrap_or_default();
+        commands.extend(args.float_placement.commands(conn, &containers)?);
+        commands.extend(
+            args.follow
+                .commands(&focus.workspace, &renames, name, &containers),
+        );
+        run_commands(conn, &commands)?;
+    }
+    args.report.print(conn, &names, &containers, Some(&plan))?;
+    if let (Some(mut new_windows), Some(name)) = (new_windows, names.first()) {
+        args.swallow.swallow(conn, &mut new_windows, name)?;
+    }
+    Ok(())
+}
+
+/// Log the planned insertion, before any command is sent
+fn log_plan(
+    pivot: &Pivot,
+    before: bool,
+    names: &[String],
+    containers: &[i64],
+    renames: &[WorkspaceRename],
+) {
+    log::debug!(
+        "Planned insertion of {} {} \"{}\" on {}, moving containers [{}], renaming {}",
+        names.join(", "),
+        if before { "before" } else { "after" },
+        pivot.name,
+        pivot.output.as_deref().unwrap_or("any output"),
+        containers
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", "),
+        renames
+            .iter()
+            .map(|x| format!("\"{}\" to \"{}\"", x.from, x.to))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+}
+
+/// Print the commands planned with `--dry-run`, one message per line
+fn print_recorded_commands(conn: &Connection) {
+    // Deliberate output contract of `--dry-run`, errors go to stderr
+    #[allow(clippy::print_stdout)]
+    for command in conn.recorded_commands() {
+        println!("{command}");
+    }
+}
+
+/// Exit code for errors without a more specific class
+const EXIT_FAILURE: u8 = 1;
+/// Exit code for invalid arguments or configuration, the same as for usage errors reported by clap
+const EXIT_USAGE: u8 = 2;
+/// Exit code if the window manager could not be reached over IPC
+const EXIT_IPC: u8 = 3;
+/// Exit code if the pivot workspace was not found or is ambiguous
+const EXIT_PIVOT: u8 = 4;
+/// Exit code if the window manager rejected a command
+const EXIT_COMMAND: u8 = 5;
+
+impl MainError {
+    /// Exit code for the class of the error, determined by the first error in the source chain with a class
+    fn exit_code(&self) -> u8 {
+        std::iter::successors(Some(self as &(dyn std::error::Error + 'static)), |x| {
+            x.source()
+        })
+        .find_map(error_class)
+        .unwrap_or(EXIT_FAILURE)
+    }
+}
+
+/// Exit code for `error`, if it belongs to a class of errors scripts can tell apart
+fn error_class(error: &(dyn std::error::Error + 'static)) -> Option<u8> {
+    if error.is::<i3ipc::EstablishError>() || error.is::<i3ipc::MessageError>() {
+        return Some(EXIT_IPC);
+    }
+    #[cfg(feature = "async")]
+    if matches!(error.downcast_ref(), Some(BackendError::Async(_)))
+        || matches!(error.downcast_ref(), Some(InsertionError::AsyncConnect(_)))
+        || matches!(error.downcast_ref(), Some(ContainerError::AsyncEvents(_)))
+    {
+        return Some(EXIT_IPC);
+    }
+    if error.is::<ConfigError>() || error.is::<regex::Error>() {
+        return Some(EXIT_USAGE);
+    }
+    if matches!(error.downcast_ref(), Some(DuplicateError::Unsupported)) {
+        return Some(EXIT_USAGE);
+    }
+    if let Some(error) = error.downcast_ref::<PivotError>() {
+        return matches!(error, PivotError::NoMatch(_) | PivotError::Ambiguous(..))
+            .then_some(EXIT_PIVOT);
+    }
+    if let Some(error) = error.downcast_ref::<CommandError>() {
+        return matches!(error, CommandError::Failed(_)).then_some(EXIT_COMMAND);
+    }
+    if let Some(error) = error.downcast_ref::<insert_workspace_swap::InsertionError>() {
+        return match error {
+            insert_workspace_swap::InsertionError::NoPivotWorkspace(_) => Some(EXIT_PIVOT),
+            insert_workspace_swap::InsertionError::CommandError(_) => Some(EXIT_COMMAND),
+            insert_workspace_swap::InsertionError::Backend(_) => None,
+        };
+    }
+    if let Some(error) = error.downcast_ref::<insert_workspace_rename::InsertionError>() {
+        return match error {
+            insert_workspace_rename::InsertionError::NoPivotWorkspace(_) => Some(EXIT_PIVOT),
+            insert_workspace_rename::InsertionError::CommandError(_) => Some(EXIT_COMMAND),
+            insert_workspace_rename::InsertionError::Backend(_) => None,
+        };
+    }
+    None
+}
+
+/// Run the command given by the command line arguments, printing errors on stderr
+#[must_use]
+pub fn main() -> ExitCode {
+    match handle() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{e}");
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
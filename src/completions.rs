@@ -0,0 +1,59 @@
+//! Generate shell completions, completing pivots with the current workspace names
+use std::io::Write;
+
+use clap_complete::Shell;
+
+use crate::{connection::Connection, util::get_workspaces};
+
+/// Hidden subcommand printing the current workspace names, called by the completion scripts
+pub const WORKSPACES_SUBCOMMAND: &str = "__complete-workspaces";
+
+/// Options of the `completions` subcommand
+#[derive(clap::Args, Debug)]
+pub struct CompletionsArgs {
+    /// Shell to generate the completion script for
+    #[clap(value_enum)]
+    shell: Shell,
+}
+
+impl CompletionsArgs {
+    /// Print the completion script for `command` on stdout
+    ///
+    /// The scripts of zsh and fish complete pivots with the workspace names printed by the hidden subcommand
+    pub fn run(&self, mut command: clap::Command) -> std::io::Result<()> {
+        let bin_name = command.get_name().to_owned();
+        let mut script = Vec::new();
+        clap_complete::generate(self.shell, &mut command, &bin_name, &mut script);
+        let mut script = String::from_utf8_lossy(&script).into_owned();
+        if self.shell == Shell::Zsh {
+            script = script.replace(
+                ":PIVOT:_default",
+                &format!(
+                    ":PIVOT:{{compadd -- ${{(f)\"$({bin_name} {WORKSPACES_SUBCOMMAND} 2>/dev/null)\"}}}}"
+                ),
+            );
+        }
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(script.as_bytes())?;
+        if self.shell == Shell::Fish {
+            for option in ["-s p -l pivot", "-l after", "-s b -l before"] {
+                writeln!(
+                    stdout,
+                    "complete -c {bin_name} {option} -x -a '({bin_name} {WORKSPACES_SUBCOMMAND} 2>/dev/null)'"
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Print the names of all workspaces, one per line
+pub fn print_workspaces(conn: &mut Connection) -> Result<(), i3ipc::MessageError> {
+    let names = get_workspaces(conn)?
+        .into_iter()
+        .map(|x| x.name + "\n")
+        .collect::<String>();
+    // A failed write means the completion was cancelled, nothing is left to report
+    let _ = std::io::stdout().write_all(names.as_bytes());
+    Ok(())
+}
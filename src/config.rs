@@ -0,0 +1,61 @@
+//! Configuration file, providing defaults for the command line options
+use std::{collections::HashMap, path::PathBuf};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    docker_name::Theme, insertion::ModeSelection, naming::NameScheme, pivot::BoundaryOutput,
+};
+
+/// Errors for `Config::load`
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error("Could not read config file: {0}")]
+    Read(#[from] std::io::Error),
+    #[error("Could not parse config file: {0}")]
+    Parse(#[from] toml::de::Error),
+}
+
+/// Contents of the configuration file
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Default for `--mode`
+    pub mode: Option<ModeSelection>,
+    /// Default for `--name-scheme`, if no name template is configured
+    pub name_scheme: Option<NameScheme>,
+    /// Default for `--name-template`
+    pub name_template: Option<String>,
+    /// Regex every name of a new workspace has to match
+    pub name_pattern: Option<String>,
+    /// Default for `--name-theme`
+    pub name_theme: Option<Theme>,
+    /// Default for `--wordlist-left`
+    pub wordlist_left: Option<PathBuf>,
+    /// Default for `--wordlist-right`
+    pub wordlist_right: Option<PathBuf>,
+    /// Default focus after the insertion, `true` like `--follow` and `false` like `--no-follow`
+    pub follow: Option<bool>,
+    /// Default for `--global-order`
+    pub global_order: Option<BoundaryOutput>,
+    /// Icons prepended to the name of the new workspace, by lowercase window class or app id of the moved container
+    pub icons: HashMap<String, String>,
+}
+
+impl Config {
+    /// Location of the configuration file
+    fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|x| x.join("i3-insert-workspace").join("config.toml"))
+    }
+
+    /// Load the configuration file
+    ///
+    /// Falls back to the default configuration if the file does not exist
+    pub fn load() -> Result<Self, ConfigError> {
+        let Some(path) = Self::path().filter(|x| x.exists()) else {
+            return Ok(Self::default());
+        };
+        Ok(toml::from_str(&std::fs::read_to_string(path)?)?)
+    }
+}
@@ -0,0 +1,208 @@
+//! Connection to the window manager, carrying the options of the requests sent on it
+use i3ipc::{
+    reply::{CommandOutcome, Output, Version, Workspace},
+    EstablishError, I3Connection, MessageError,
+};
+
+use crate::retry::RetryPolicy;
+
+/// Connection to the window manager through `i3ipc`
+///
+/// In dry-run mode commands are recorded instead of run.
+/// Otherwise they are only recorded if asked for, e.g. to report them
+pub struct Connection {
+    conn: I3Connection,
+    /// Retrying of connecting and of requests failing on a lost connection
+    retry: RetryPolicy,
+    /// Only record commands instead of running them
+    dry_run: bool,
+    /// Command messages sent so far, or only planned in dry-run mode, if recording
+    recorded: Option<Vec<String>>,
+    /// Insert and wait for new windows through `tokio-i3ipc`
+    #[cfg(feature = "async")]
+    async_ipc: bool,
+}
+
+impl Connection {
+    /// Connect to the window manager at the socket given by `I3SOCK`, or by `i3 --get-socketpath`
+    ///
+    /// Connecting, and requests on the connection, are retried following `retry`
+    ///
+    /// # Errors
+    ///
+    /// Fails if the socket of the window manager can not be found or connected to
+    pub fn connect(retry: RetryPolicy) -> Result<Self, EstablishError> {
+        Ok(Self {
+            conn: retry.retry("Connecting", |_| I3Connection::connect(), |_| true)?,
+            retry,
+            dry_run: false,
+            recorded: None,
+            #[cfg(feature = "async")]
+            async_ipc: false,
+        })
+    }
+
+    /// Retrying of the requests on this connection, for requests sent on other connections
+    #[must_use]
+    pub fn retry_policy(&self) -> RetryPolicy {
+        self.retry
+    }
+
+    /// Only record commands instead of running them, if `dry_run` is set
+    pub fn set_dry_run(&mut self, dry_run: bool) {
+        self.dry_run = dry_run;
+        if dry_run {
+            self.record_commands();
+        }
+    }
+
+    /// Whether commands are only recorded instead of run
+    #[must_use]
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    /// Insert and wait for new windows through `tokio-i3ipc`, if `async_ipc` is set
+    #[cfg(feature = "async")]
+    pub fn set_async_ipc(&mut self, async_ipc: bool) {
+        self.async_ipc = async_ipc;
+    }
+
+    /// Whether insertions and new windows go through `tokio-i3ipc`
+    #[cfg(feature = "async")]
+    #[must_use]
+    pub fn uses_async_ipc(&self) -> bool {
+        self.async_ipc
+    }
+
+    /// Record the command messages from now on
+    pub fn record_commands(&mut self) {
+        self.recorded.get_or_insert_with(Vec::new);
+    }
+
+    /// Command messages sent so far, or only planned in dry-run mode, since recording started
+    #[must_use]
+    pub fn recorded_commands(&self) -> &[String] {
+        self.recorded.as_deref().unwrap_or_default()
+    }
+
+    /// Record `command` as sent, if recording
+    ///
+    /// For commands sent on another connection to the same window manager
+    pub(crate) fn record(&mut self, command: &str) {
+        if let Some(recorded) = &mut self.recorded {
+            recorded.push(command.to_owned());
+        }
+    }
+
+    /// Send `command`, returning the outcome of each command in it
+    ///
+    /// In dry-run mode the command is only recorded, and no outcomes are returned.
+    /// A command which was sent is not retried, even if its reply was lost, as it might have run already
+    ///
+    /// # Errors
+    ///
+    /// Fails if the command can not be sent, not if it fails to run
+    pub fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, MessageError> {
+        self.record(command);
+        if self.dry_run {
+            log::info!("Planned command: {command}");
+            return Ok(Vec::new());
+        }
+        log::info!("Running command: {command}");
+        let outcomes = self
+            .request(
+                "Sending command",
+                |conn| conn.run_command(command),
+                |error| matches!(error, MessageError::Send(_)),
+            )?
+            .outcomes;
+        log_outcomes(&outcomes);
+        Ok(outcomes)
+    }
+
+    /// Get the workspaces, in the order of their outputs
+    ///
+    /// # Errors
+    ///
+    /// Fails if the window manager can not be queried
+    pub fn get_workspaces(&mut self) -> Result<Vec<Workspace>, MessageError> {
+        Ok(self
+            .query("Requesting workspaces", I3Connection::get_workspaces)?
+            .workspaces)
+    }
+
+    /// Get the outputs
+    ///
+    /// # Errors
+    ///
+    /// Fails if the window manager can not be queried
+    pub fn get_outputs(&mut self) -> Result<Vec<Output>, MessageError> {
+        Ok(self
+            .query("Requesting outputs", I3Connection::get_outputs)?
+            .outputs)
+    }
+
+    /// Get the version of the window manager, with the path of its loaded config file
+    ///
+    /// # Errors
+    ///
+    /// Fails if the window manager can not be queried
+    pub fn get_version(&mut self) -> Result<Version, MessageError> {
+        self.query("Requesting version", I3Connection::get_version)
+    }
+
+    /// Run the query `request`, retrying it on a new connection if the connection was lost
+    fn query<T>(
+        &mut self,
+        what: &str,
+        request: impl FnMut(&mut I3Connection) -> Result<T, MessageError>,
+    ) -> Result<T, MessageError> {
+        log::debug!("{what}");
+        self.request(what, request, |error| {
+            matches!(error, MessageError::Send(_) | MessageError::Receive(_))
+        })
+    }
+
+    /// Run `request`, retrying it on a new connection if it fails with a `transient` error
+    fn request<T>(
+        &mut self,
+        what: &str,
+        mut request: impl FnMut(&mut I3Connection) -> Result<T, MessageError>,
+        transient: impl Fn(&MessageError) -> bool,
+    ) -> Result<T, MessageError> {
+        let conn = &mut self.conn;
+        self.retry.retry(
+            what,
+            |retrying| {
+                if retrying {
+                    reconnect(conn);
+                }
+                request(conn)
+            },
+            transient,
+        )
+    }
+}
+
+/// Replace `conn` with a new connection, keeping it if connecting fails
+fn reconnect(conn: &mut I3Connection) {
+    match I3Connection::connect() {
+        Ok(new) => *conn = new,
+        Err(error) => log::debug!("Could not reconnect: {error}"),
+    }
+}
+
+/// Log the outcome of each sent command
+pub fn log_outcomes(outcomes: &[CommandOutcome]) {
+    for outcome in outcomes {
+        if outcome.success {
+            log::debug!("Command succeeded");
+        } else {
+            log::debug!(
+                "Command failed: {}",
+                outcome.error.as_deref().unwrap_or("no error message")
+            );
+        }
+    }
+}
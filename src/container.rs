@@ -0,0 +1,635 @@
+//! Select the containers moved to the new workspace
+use std::{
+    io::Write,
+    process::{Command, Stdio},
+    sync::mpsc,
+    time::{Duration, Instant},
+};
+
+use i3ipc::{
+    event::{inner::WindowChange, Event},
+    I3EventListener, Subscription,
+};
+use thiserror::Error;
+
+#[cfg(feature = "async")]
+use crate::async_ipc::WindowEvents;
+use crate::{
+    connection::Connection,
+    criteria::{self, CriteriaError},
+    raw_tree::{self, RawNode, RawTreeError},
+    retry::RetryPolicy,
+    util::{run_commands, CommandError, SCRATCHPAD_WORKSPACE},
+};
+
+/// Time to wait for a new window, when selecting the `latest` container
+const LATEST_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Errors while selecting containers
+#[derive(Debug, Error)]
+pub enum ContainerError {
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Criteria error: {0}")]
+    Criteria(#[from] CriteriaError),
+    #[error("No container holds window {0:#x}")]
+    WindowNotFound(u64),
+    #[error("No window of class \"{0}\"")]
+    NoWindowOfClass(String),
+    #[error("The scratchpad is empty")]
+    EmptyScratchpad,
+    #[error("Container {0} is not inside a workspace")]
+    ContainerNotFound(i64),
+    #[error("Could not listen to window events: {0}")]
+    EventListener(#[from] i3ipc::EstablishError),
+    #[error("Could not subscribe to window events: {0}")]
+    Subscribe(#[from] i3ipc::MessageError),
+    #[cfg(feature = "async")]
+    #[error("Could not listen to window events asynchronously: {0}")]
+    AsyncEvents(#[source] std::io::Error),
+    #[error("No new window appeared within {0} seconds")]
+    NoNewWindow(u64),
+    #[error("Could not run window picker: {0}")]
+    Picker(std::io::Error),
+    #[error("No window was picked")]
+    PickCancelled,
+    #[error("Container {0} is sticky")]
+    Sticky(i64),
+    #[error("Could not move the swallowed window: {0}")]
+    Command(#[from] CommandError),
+    #[error("Invalid container: {0}")]
+    InvalidContainer(String),
+    #[error("Non-numeric container id: {0}")]
+    ParseCointainerID(
+        #[from]
+        #[source]
+        std::num::ParseIntError,
+    ),
+}
+
+/// Handling of sticky floating containers, which are shown on all workspaces
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum StickyHandling {
+    /// Leave sticky containers where they are
+    Keep,
+    /// Make sticky containers non-sticky and move them
+    Move,
+    /// Fail if a sticky container is selected
+    Error,
+}
+
+/// Options selecting the containers moved to the new workspace
+///
+/// All selected containers are combined, each container is moved once
+#[derive(clap::Args, Debug)]
+pub struct ContainerSelection {
+    /// Move containers to the new workspace.
+    ///
+    /// Either provide container id, `focused` for focused one, or `focused-parent[:N]` for the
+    /// container N levels above the focused one, including its split or tabbed layout.
+    /// N defaults to 1, the parent is at most the topmost container of the workspace.
+    /// `focused-group` is the tabbed or stacked container holding the focused one, or the focused one if it is in no such group.
+    /// `latest` waits a few seconds for the next new window, like one started together with this program.
+    /// Repeat the option or separate ids by commas to move several containers
+    #[clap(
+        short,
+        long,
+        env = "I3IW_CONTAINER_ID",
+        visible_alias = "container",
+        value_delimiter = ','
+    )]
+    container_id: Vec<String>,
+
+    /// Move the containers holding X11 windows to the new workspace, like `0x3a00007`.
+    ///
+    /// Window ids as printed by `xdotool` or `xwininfo`, in hexadecimal with `0x` prefix or decimal
+    #[clap(long, env = "I3IW_WINDOW_ID", value_delimiter = ',', value_parser = parse_window_id)]
+    window_id: Vec<u64>,
+
+    /// Move all windows with this X11 window class or wayland app id to the new workspace, ignoring case
+    #[clap(long, env = "I3IW_MOVE_ALL_OF_CLASS")]
+    move_all_of_class: Option<String>,
+
+    /// Move the containers matching criteria to the new workspace, like `[app_id="foot" title=".*scratch.*"]`
+    #[clap(long, env = "I3IW_CONTAINER_CRITERIA")]
+    container_criteria: Option<String>,
+
+    /// Move the first window of the scratchpad to the new workspace
+    #[clap(long, env = "I3IW_FROM_SCRATCHPAD")]
+    from_scratchpad: bool,
+
+    /// Pick a window to move from a list of all windows.
+    ///
+    /// Either `dmenu`, `rofi`, or a shell command reading the list from stdin and printing the picked line
+    #[clap(
+        long, env = "I3IW_CONTAINER_PICK",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "rofi",
+        value_name = "PICKER"
+    )]
+    container_pick: Option<String>,
+
+    /// What to do with selected sticky floating containers
+    #[clap(long, env = "I3IW_STICKY", value_enum, default_value_t = StickyHandling::Move)]
+    sticky: StickyHandling,
+}
+
+impl ContainerSelection {
+    /// Resolve the ids of the selected containers, `focused` being the focused container
+    ///
+    /// Returns the containers to move, and the sticky ones among them to make non-sticky when moving them
+    pub fn resolve(
+        self,
+        conn: &mut Connection,
+        focused: i64,
+    ) -> Result<(Vec<i64>, Vec<i64>), ContainerError> {
+        let mut containers = self
+            .container_id
+            .iter()
+            .map(|x| parse_container_id(conn, x, focused))
+            .collect::<Result<Vec<_>, _>>()?;
+        if !self.window_id.is_empty() {
+            let tree = raw_tree::get_tree(conn)?;
+            for window in self.window_id {
+                let container = tree
+                    .find_window(window)
+                    .ok_or(ContainerError::WindowNotFound(window))?;
+                containers.push(container.id);
+            }
+        }
+        if let Some(class) = self.move_all_of_class {
+            let mut windows = Vec::new();
+            raw_tree::get_tree(conn)?.windows_of_class(&class, &mut windows);
+            if windows.is_empty() {
+                return Err(ContainerError::NoWindowOfClass(class));
+            }
+            containers.extend(windows);
+        }
+        if self.from_scratchpad {
+            let tree = raw_tree::get_tree(conn)?;
+            let window = tree
+                .descendants()
+                .into_iter()
+                .find(|x| x.name.as_deref() == Some(SCRATCHPAD_WORKSPACE))
+                .and_then(|x| x.floating_nodes.first())
+                .ok_or(ContainerError::EmptyScratchpad)?;
+            containers.push(window.id);
+        }
+        if let Some(picker) = &self.container_pick {
+            containers.push(pick_window(conn, picker)?);
+        }
+        if let Some(criteria) = self.container_criteria {
+            containers.extend(criteria::matching_containers(conn, &criteria)?);
+        }
+
+        let mut unique = Vec::with_capacity(containers.len());
+        for container in containers {
+            if !unique.contains(&container) {
+                unique.push(container);
+            }
+        }
+        if unique.is_empty() {
+            return Ok((unique, Vec::new()));
+        }
+        self.sticky.apply(&raw_tree::get_tree(conn)?, unique)
+    }
+}
+
+impl StickyHandling {
+    /// Handle the sticky ones of `containers` in `tree`
+    ///
+    /// Returns the containers to move, and the sticky ones among them to make non-sticky
+    fn apply(
+        self,
+        tree: &RawNode,
+        containers: Vec<i64>,
+    ) -> Result<(Vec<i64>, Vec<i64>), ContainerError> {
+        // i3 sets the flag on the floating container around the window
+        let is_sticky = |container: &i64| {
+            tree.path_to(*container)
+                .is_some_and(|path| path.iter().any(|x| x.sticky))
+        };
+        match self {
+            Self::Keep => Ok((
+                containers.into_iter().filter(|x| !is_sticky(x)).collect(),
+                Vec::new(),
+            )),
+            Self::Error => {
+                if let Some(sticky) = containers.iter().find(|x| is_sticky(x)) {
+                    return Err(ContainerError::Sticky(*sticky));
+                }
+                Ok((containers, Vec::new()))
+            }
+            Self::Move => {
+                let sticky = containers
+                    .iter()
+                    .copied()
+                    .filter(|x| is_sticky(x))
+                    .collect();
+                Ok((containers, sticky))
+            }
+        }
+    }
+}
+
+/// Parse a container given to `--container-id`
+fn parse_container_id(
+    conn: &mut Connection,
+    container_id: &str,
+    focused: i64,
+) -> Result<i64, ContainerError> {
+    let container_id = container_id.to_ascii_lowercase();
+    if container_id == "focused" {
+        return Ok(focused);
+    }
+    if container_id == "focused-group" {
+        return group(&raw_tree::get_tree(conn)?, focused);
+    }
+    if container_id == "latest" {
+        return NewWindows::subscribe(conn)?.next(LATEST_TIMEOUT, |_| Ok(true));
+    }
+    if let Some(levels) = container_id.strip_prefix("focused-parent") {
+        let levels = match levels.strip_prefix(':') {
+            Some(levels) => levels.parse()?,
+            None if levels.is_empty() => 1,
+            None => return Err(ContainerError::InvalidContainer(container_id)),
+        };
+        return ancestor(&raw_tree::get_tree(conn)?, focused, levels);
+    }
+    Ok(container_id.parse::<i64>()?)
+}
+
+/// Let the user pick a window using `picker`, and get its container
+///
+/// `picker` is either `dmenu`, `rofi`, or a shell command reading the list of windows from stdin
+fn pick_window(conn: &mut Connection, picker: &str) -> Result<i64, ContainerError> {
+    const PROMPT_TEXT: &str = "Window";
+    let tree = raw_tree::get_tree(conn)?;
+    let windows = tree
+        .descendants()
+        .into_iter()
+        .filter(|x| x.nodetype == "workspace")
+        .flat_map(|workspace| {
+            let workspace_name = workspace.name.clone().unwrap_or_default();
+            workspace
+                .descendants()
+                .into_iter()
+                .filter(|x| x.window.is_some() || x.app_id.is_some())
+                .map(move |x| {
+                    let line = format!(
+                        "{workspace_name}: {} ({})",
+                        x.name.as_deref().unwrap_or_default(),
+                        x.class().unwrap_or_default()
+                    );
+                    (line, x.id)
+                })
+        })
+        .collect::<Vec<_>>();
+
+    let mut command = match picker {
+        "dmenu" => {
+            let mut command = Command::new("dmenu");
+            command.args(["-i", "-l", "20", "-p", PROMPT_TEXT]);
+            command
+        }
+        "rofi" => {
+            let mut command = Command::new("rofi");
+            command.args(["-dmenu", "-i", "-p", PROMPT_TEXT]);
+            command
+        }
+        custom => {
+            let mut command = Command::new("sh");
+            command.args(["-c", custom]);
+            command
+        }
+    };
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .map_err(ContainerError::Picker)?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let list = windows
+            .iter()
+            .map(|(line, _)| line.as_str())
+            .collect::<Vec<_>>()
+            .join("\n");
+        stdin
+            .write_all(list.as_bytes())
+            .map_err(ContainerError::Picker)?;
+    }
+    let output = child.wait_with_output().map_err(ContainerError::Picker)?;
+    let selection = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if !output.status.success() {
+        return Err(ContainerError::PickCancelled);
+    }
+    windows
+        .into_iter()
+        .find(|(line, _)| line.trim() == selection)
+        .map(|(_, id)| id)
+        .ok_or(ContainerError::PickCancelled)
+}
+
+/// New windows reported by the window manager, from the moment of the subscription on
+pub enum NewWindows {
+    /// Sent by a listener thread
+    Thread(mpsc::Receiver<i64>),
+    /// Read from the event stream of `tokio-i3ipc`
+    #[cfg(feature = "async")]
+    Async(WindowEvents),
+}
+
+impl NewWindows {
+    /// Subscribe to window events, retrying like the requests on `conn`, through `tokio-i3ipc` if `conn` uses it
+    ///
+    /// A lost event connection is replaced by a new subscription, missing the windows in between
+    pub fn subscribe(conn: &Connection) -> Result<Self, ContainerError> {
+        let retry = conn.retry_policy();
+        #[cfg(feature = "async")]
+        if conn.uses_async_ipc() {
+            let events = WindowEvents::subscribe(retry).map_err(ContainerError::AsyncEvents)?;
+            return Ok(Self::Async(events));
+        }
+        let mut listener = Self::listener(retry)?;
+        let (sender, receiver) = mpsc::channel();
+        // The listener blocks until the next event, so it is left behind in its thread after a timeout
+        std::thread::spawn(move || loop {
+            for event in listener.listen() {
+                let container = match event {
+                    Ok(Event::WindowEvent(info)) if info.change == WindowChange::New => {
+                        info.container.id
+                    }
+                    Ok(_) => continue,
+                    Err(error) => {
+                        log::debug!("Lost window events: {error}");
+                        break;
+                    }
+                };
+                if sender.send(container).is_err() {
+                    // Nobody waits for new windows anymore
+                    return;
+                }
+            }
+            match Self::listener(retry) {
+                Ok(new) => listener = new,
+                Err(error) => {
+                    log::warn!("Could not resubscribe to window events: {error}");
+                    return;
+                }
+            }
+        });
+        Ok(Self::Thread(receiver))
+    }
+
+    /// Connect and subscribe to window events, following `retry`
+    fn listener(retry: RetryPolicy) -> Result<I3EventListener, ContainerError> {
+        retry.retry(
+            "Subscribing to window events",
+            |_| {
+                let mut listener = I3EventListener::connect()?;
+                listener.subscribe(&[Subscription::Window])?;
+                Ok(listener)
+            },
+            |error| {
+                matches!(
+                    error,
+                    ContainerError::EventListener(_) | ContainerError::Subscribe(_)
+                )
+            },
+        )
+    }
+
+    /// Wait for the next new window accepted by `accept`, and get its container
+    ///
+    /// Fails if no such window appears within `timeout`
+    pub fn next(
+        &mut self,
+        timeout: Duration,
+        mut accept: impl FnMut(i64) -> Result<bool, ContainerError>,
+    ) -> Result<i64, ContainerError> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let container = match self {
+                Self::Thread(receiver) => receiver
+                    .recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    .ok(),
+                #[cfg(feature = "async")]
+                Self::Async(events) => events.next(deadline),
+            }
+            .ok_or(ContainerError::NoNewWindow(timeout.as_secs()))?;
+            if accept(container)? {
+                return Ok(container);
+            }
+        }
+    }
+}
+
+/// Options routing the next new window into the new workspace
+#[derive(clap::Args, Debug)]
+pub struct SwallowArgs {
+    /// Move the next new window to the new workspace, optionally only one matching criteria like `[class="Firefox"]`.
+    ///
+    /// Useful to insert a workspace and then start an application, which takes a while to show its window
+    #[clap(
+        long, env = "I3IW_SWALLOW",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "",
+        value_name = "CRITERIA"
+    )]
+    swallow: Option<String>,
+
+    /// Seconds to wait for the window to swallow
+    #[clap(long, env = "I3IW_TIMEOUT", requires = "swallow", default_value_t = 10)]
+    timeout: u64,
+}
+
+impl SwallowArgs {
+    /// Start listening for new windows, if a window should be swallowed
+    pub fn subscribe(&self, conn: &Connection) -> Result<Option<NewWindows>, ContainerError> {
+        self.swallow
+            .as_ref()
+            .map(|_| NewWindows::subscribe(conn))
+            .transpose()
+    }
+
+    /// Wait for the window to swallow, and move it to the workspace `name`
+    pub fn swallow(
+        &self,
+        conn: &mut Connection,
+        new_windows: &mut NewWindows,
+        name: &str,
+    ) -> Result<(), ContainerError> {
+        let criteria = self.swallow.as_deref().filter(|x| !x.trim().is_empty());
+        let container = new_windows.next(Duration::from_secs(self.timeout), |container| {
+            criteria.map_or(Ok(true), |criteria| {
+                match criteria::matching_containers(conn, criteria) {
+                    Ok(matching) => Ok(matching.contains(&container)),
+                    Err(CriteriaError::NoMatch(_)) => Ok(false),
+                    Err(e) => Err(e.into()),
+                }
+            })
+        })?;
+        run_commands(
+            conn,
+            &[format!(
+                "[con_id={container}] move container to workspace \"{name}\""
+            )],
+        )?;
+        Ok(())
+    }
+}
+
+/// Parse an X11 window id, in hexadecimal with `0x` prefix or decimal
+fn parse_window_id(window_id: &str) -> Result<u64, std::num::ParseIntError> {
+    window_id
+        .strip_prefix("0x")
+        .or_else(|| window_id.strip_prefix("0X"))
+        .map_or_else(|| window_id.parse(), |hex| u64::from_str_radix(hex, 16))
+}
+
+/// Get the container `levels` above `container` in `tree`, stopping at the topmost container of its workspace
+fn ancestor(tree: &RawNode, container: i64, levels: usize) -> Result<i64, ContainerError> {
+    let path = tree
+        .path_to(container)
+        .ok_or(ContainerError::ContainerNotFound(container))?;
+    // Containers between the workspace and `container`, with `container` last
+    let below_workspace = path
+        .iter()
+        .rposition(|x| x.nodetype == "workspace")
+        .and_then(|workspace| path.get(workspace + 1..))
+        .filter(|x| !x.is_empty())
+        .ok_or(ContainerError::ContainerNotFound(container))?;
+    let index = below_workspace.len().saturating_sub(levels + 1);
+    below_workspace
+        .get(index)
+        .map(|x| x.id)
+        .ok_or(ContainerError::ContainerNotFound(container))
+}
+
+/// Get the tabbed or stacked container in `tree` directly holding `container`, or `container` if there is none
+///
+/// A tabbed or stacked workspace is no group, as workspaces can not be moved as a container
+fn group(tree: &RawNode, container: i64) -> Result<i64, ContainerError> {
+    let path = tree
+        .path_to(container)
+        .ok_or(ContainerError::ContainerNotFound(container))?;
+    let parent = path.iter().rev().nth(1);
+    Ok(parent
+        .filter(|x| x.nodetype != "workspace")
+        .filter(|x| x.layout == "tabbed" || x.layout == "stacked")
+        .map_or(container, |x| x.id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ancestor, group, parse_window_id, ContainerError, StickyHandling};
+    use crate::raw_tree::RawNode;
+
+    fn node(id: i64, nodetype: &str, layout: &str, nodes: Vec<RawNode>) -> RawNode {
+        RawNode {
+            id,
+            nodetype: nodetype.to_owned(),
+            layout: layout.to_owned(),
+            nodes,
+            ..RawNode::default()
+        }
+    }
+
+    fn window(id: i64) -> RawNode {
+        RawNode {
+            window: u64::try_from(id).ok(),
+            ..node(id, "con", "splith", Vec::new())
+        }
+    }
+
+    /// Workspace 10 holding the tabbed container 20 with the windows 21 and 22, the window 23,
+    /// and the sticky floating container 30 around the window 31
+    fn tree() -> RawNode {
+        let mut workspace = node(
+            10,
+            "workspace",
+            "splith",
+            vec![
+                node(20, "con", "tabbed", vec![window(21), window(22)]),
+                window(23),
+            ],
+        );
+        workspace.floating_nodes.push(RawNode {
+            sticky: true,
+            ..node(30, "floating_con", "splith", vec![window(31)])
+        });
+        let content = node(3, "con", "splith", vec![workspace]);
+        node(
+            1,
+            "root",
+            "splith",
+            vec![node(2, "output", "output", vec![content])],
+        )
+    }
+
+    #[test]
+    fn parses_window_ids() {
+        assert_eq!(parse_window_id("0x3a00007").ok(), Some(0x3a0_0007));
+        assert_eq!(parse_window_id("0X1f").ok(), Some(31));
+        assert_eq!(parse_window_id("42").ok(), Some(42));
+        assert!(parse_window_id("0xg").is_err());
+        assert!(parse_window_id("window").is_err());
+    }
+
+    #[test]
+    fn finds_ancestors_below_the_workspace() -> Result<(), ContainerError> {
+        let tree = tree();
+        assert_eq!(ancestor(&tree, 21, 0)?, 21);
+        assert_eq!(ancestor(&tree, 21, 1)?, 20);
+        assert_eq!(ancestor(&tree, 21, 5)?, 20);
+        assert_eq!(ancestor(&tree, 23, 1)?, 23);
+        assert!(matches!(
+            ancestor(&tree, 99, 1),
+            Err(ContainerError::ContainerNotFound(99))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn finds_tabbed_groups() -> Result<(), ContainerError> {
+        let mut tree = tree();
+        assert_eq!(group(&tree, 22)?, 20);
+        assert_eq!(group(&tree, 23)?, 23);
+        // A tabbed workspace is no group
+        if let Some(workspace) = tree
+            .nodes
+            .iter_mut()
+            .flat_map(|x| x.nodes.iter_mut())
+            .flat_map(|x| x.nodes.iter_mut())
+            .next()
+        {
+            workspace.layout = "tabbed".to_owned();
+        }
+        assert_eq!(group(&tree, 23)?, 23);
+        Ok(())
+    }
+
+    #[test]
+    fn handles_sticky_containers() -> Result<(), ContainerError> {
+        let tree = tree();
+        assert_eq!(
+            StickyHandling::Keep.apply(&tree, vec![21, 31])?,
+            (vec![21], Vec::new())
+        );
+        assert_eq!(
+            StickyHandling::Move.apply(&tree, vec![21, 31])?,
+            (vec![21, 31], vec![31])
+        );
+        assert!(matches!(
+            StickyHandling::Error.apply(&tree, vec![21, 31]),
+            Err(ContainerError::Sticky(31))
+        ));
+        assert_eq!(
+            StickyHandling::Error.apply(&tree, vec![21])?,
+            (vec![21], Vec::new())
+        );
+        Ok(())
+    }
+}
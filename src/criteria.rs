@@ -0,0 +1,320 @@
+//! Match i3 criteria against the container tree
+use regex::Regex;
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    raw_tree::{self, RawNode, RawTreeError},
+};
+
+/// Errors while resolving criteria
+#[derive(Debug, Error)]
+pub enum CriteriaError {
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Malformed criteria \"{0}\"")]
+    Malformed(String),
+    #[error("Unsupported criterion \"{0}\"")]
+    Unsupported(String),
+    #[error("Invalid regular expression in criteria: {0}")]
+    Regex(#[from] regex::Error),
+    #[error("No container matches criteria \"{0}\"")]
+    NoMatch(String),
+}
+
+/// Single criterion of i3 criteria
+#[derive(Debug)]
+enum Criterion {
+    Class(Regex),
+    Instance(Regex),
+    WindowRole(Regex),
+    Title(Regex),
+    AppId(Regex),
+    Workspace(Regex),
+    Mark(Regex),
+    ConId(i64),
+    Window(u64),
+    Floating,
+    Tiling,
+    All,
+}
+
+impl Criterion {
+    /// Parse the criterion `key`, with `value` if it was given one
+    fn parse(key: &str, value: Option<&str>) -> Result<Self, CriteriaError> {
+        if value == Some("__focused__") {
+            return Err(CriteriaError::Unsupported(format!("{key}=__focused__")));
+        }
+        let unsupported = || CriteriaError::Unsupported(key.to_owned());
+        let Some(value) = value else {
+            return match key {
+                "floating" => Ok(Self::Floating),
+                "tiling" => Ok(Self::Tiling),
+                "all" => Ok(Self::All),
+                _ => Err(unsupported()),
+            };
+        };
+        let malformed = || CriteriaError::Malformed(format!("{key}={value}"));
+        Ok(match key {
+            "class" => Self::Class(Regex::new(value)?),
+            "instance" => Self::Instance(Regex::new(value)?),
+            "window_role" => Self::WindowRole(Regex::new(value)?),
+            "title" => Self::Title(Regex::new(value)?),
+            "app_id" => Self::AppId(Regex::new(value)?),
+            "workspace" => Self::Workspace(Regex::new(value)?),
+            "con_mark" => Self::Mark(Regex::new(value)?),
+            "con_id" => Self::ConId(value.parse().map_err(|_| malformed())?),
+            "id" => Self::Window(
+                value
+                    .strip_prefix("0x")
+                    .map_or_else(|| value.parse(), |hex| u64::from_str_radix(hex, 16))
+                    .map_err(|_| malformed())?,
+            ),
+            _ => return Err(unsupported()),
+        })
+    }
+
+    /// Check if `node` matches, which is inside the workspace `workspace`, and floating if `floating`
+    ///
+    /// Only `con_id` and `con_mark` match containers without a window
+    fn matches(&self, node: &RawNode, workspace: Option<&str>, floating: bool) -> bool {
+        let is_window = node.window.is_some() || node.app_id.is_some();
+        let properties = node.window_properties.as_ref();
+        let is_match =
+            |regex: &Regex, value: Option<&str>| value.is_some_and(|x| regex.is_match(x));
+        match self {
+            Self::Class(regex) => is_match(regex, properties.and_then(|x| x.class.as_deref())),
+            Self::Instance(regex) => {
+                is_match(regex, properties.and_then(|x| x.instance.as_deref()))
+            }
+            Self::WindowRole(regex) => {
+                is_match(regex, properties.and_then(|x| x.window_role.as_deref()))
+            }
+            Self::Title(regex) => is_window && is_match(regex, node.name.as_deref()),
+            Self::AppId(regex) => is_match(regex, node.app_id.as_deref()),
+            Self::Workspace(regex) => is_window && is_match(regex, workspace),
+            Self::Mark(regex) => node.marks.iter().any(|x| regex.is_match(x)),
+            Self::ConId(id) => node.id == *id,
+            Self::Window(window) => node.window == Some(*window),
+            Self::Floating => is_window && floating,
+            Self::Tiling => is_window && !floating,
+            Self::All => is_window,
+        }
+    }
+}
+
+/// i3 criteria like `[class="Slack" title="^general"]`, matched against the container tree
+///
+/// The values are regular expressions, except for `con_id` and `id`. All criteria have to match
+#[derive(Debug)]
+pub struct Criteria {
+    criteria: Vec<Criterion>,
+}
+
+impl Criteria {
+    /// Parse the criteria, with or without the surrounding brackets
+    pub fn parse(criteria: &str) -> Result<Self, CriteriaError> {
+        let malformed = || CriteriaError::Malformed(criteria.to_owned());
+        let mut rest = criteria
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .trim_start();
+        let mut parsed = Vec::new();
+        while !rest.is_empty() {
+            let key_end = rest
+                .find(|x: char| x == '=' || x.is_whitespace())
+                .unwrap_or(rest.len());
+            let (key, after_key) = rest.split_at(key_end);
+            let (value, after_value) = match after_key.strip_prefix('=') {
+                Some(value) => {
+                    let (value, after_value) = split_value(value).ok_or_else(malformed)?;
+                    (Some(value), after_value)
+                }
+                None => (None, after_key),
+            };
+            parsed.push(Criterion::parse(key, value.as_deref())?);
+            rest = after_value.trim_start();
+        }
+        if parsed.is_empty() {
+            return Err(malformed());
+        }
+        Ok(Self { criteria: parsed })
+    }
+
+    /// Get the ids of the containers below `node` matching all criteria
+    #[must_use]
+    pub fn matching(&self, node: &RawNode) -> Vec<i64> {
+        let mut ids = Vec::new();
+        self.collect(node, None, false, &mut ids);
+        ids
+    }
+
+    fn collect(&self, node: &RawNode, workspace: Option<&str>, floating: bool, ids: &mut Vec<i64>) {
+        let workspace = if node.nodetype == "workspace" {
+            node.name.as_deref()
+        } else {
+            workspace
+        };
+        let is_container = node.nodetype == "con" || node.nodetype == "floating_con";
+        if is_container
+            && self
+                .criteria
+                .iter()
+                .all(|x| x.matches(node, workspace, floating))
+        {
+            ids.push(node.id);
+        }
+        for child in &node.nodes {
+            self.collect(child, workspace, floating, ids);
+        }
+        for child in &node.floating_nodes {
+            self.collect(child, workspace, true, ids);
+        }
+    }
+}
+
+/// Split a criterion value, quoted or up to the next whitespace, from the following criteria
+///
+/// Returns `None` for an unterminated quote
+fn split_value(text: &str) -> Option<(String, &str)> {
+    let Some(quoted) = text.strip_prefix('"') else {
+        let end = text.find(char::is_whitespace).unwrap_or(text.len());
+        let (value, rest) = text.split_at(end);
+        return Some((value.to_owned(), rest));
+    };
+    let mut escaped = false;
+    let end = quoted.char_indices().find_map(|(index, character)| {
+        let end = character == '"' && !escaped;
+        escaped = character == '\\' && !escaped;
+        end.then_some(index)
+    })?;
+    let (value, rest) = quoted.split_at(end);
+    Some((
+        value.replace("\\\"", "\""),
+        rest.get(1..).unwrap_or_default(),
+    ))
+}
+
+/// Get the ids of the containers matching `criteria`, like `[class="Slack"]`
+///
+/// The criteria are matched against the container tree, without sending commands to the window manager
+pub fn matching_containers(
+    conn: &mut Connection,
+    criteria: &str,
+) -> Result<Vec<i64>, CriteriaError> {
+    let parsed = Criteria::parse(criteria)?;
+    let ids = parsed.matching(&raw_tree::get_tree(conn)?);
+    if ids.is_empty() {
+        return Err(CriteriaError::NoMatch(criteria.trim().to_owned()));
+    }
+    Ok(ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Criteria, CriteriaError};
+    use crate::raw_tree::{RawNode, WindowProperties};
+
+    fn window(id: i64, class: &str, title: &str) -> RawNode {
+        RawNode {
+            id,
+            name: Some(title.to_owned()),
+            nodetype: "con".to_owned(),
+            window: Some(u64::try_from(id).unwrap_or_default() + 0x100),
+            window_properties: Some(WindowProperties {
+                class: Some(class.to_owned()),
+                ..WindowProperties::default()
+            }),
+            ..RawNode::default()
+        }
+    }
+
+    fn workspace(
+        id: i64,
+        name: &str,
+        nodes: Vec<RawNode>,
+        floating_nodes: Vec<RawNode>,
+    ) -> RawNode {
+        RawNode {
+            id,
+            name: Some(name.to_owned()),
+            nodetype: "workspace".to_owned(),
+            nodes,
+            floating_nodes,
+            ..RawNode::default()
+        }
+    }
+
+    fn tree() -> RawNode {
+        let mut marked = window(3, "Slack", "random - Slack");
+        marked.marks.push("chat".to_owned());
+        RawNode {
+            nodetype: "root".to_owned(),
+            nodes: vec![
+                workspace(
+                    10,
+                    "1",
+                    vec![
+                        window(1, "Firefox", "Mozilla Firefox"),
+                        window(2, "Slack", "general - Slack"),
+                    ],
+                    Vec::new(),
+                ),
+                workspace(20, "2: chat", Vec::new(), vec![marked]),
+            ],
+            ..RawNode::default()
+        }
+    }
+
+    fn matching(criteria: &str) -> Result<Vec<i64>, CriteriaError> {
+        Ok(Criteria::parse(criteria)?.matching(&tree()))
+    }
+
+    #[test]
+    fn matches_properties() -> Result<(), CriteriaError> {
+        assert_eq!(matching("[class=\"Slack\"]")?, [2, 3]);
+        assert_eq!(matching("[class=\"^Slack$\" title=\"^general\"]")?, [2]);
+        assert_eq!(matching("[workspace=\"chat\"]")?, [3]);
+        assert_eq!(matching("[con_mark=chat]")?, [3]);
+        assert_eq!(matching("[con_id=1]")?, [1]);
+        assert_eq!(matching("[id=0x102]")?, [2]);
+        assert_eq!(matching("[title=\"Slack\" floating]")?, [3]);
+        assert_eq!(matching("[tiling]")?, [1, 2]);
+        assert_eq!(matching("all")?, [1, 2, 3]);
+        assert!(matching("[class=\"Thunderbird\"]")?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn parses_quoted_values() -> Result<(), CriteriaError> {
+        assert_eq!(matching(r#"[title="Mozilla Firefox"]"#)?, [1]);
+        assert_eq!(matching(r#"[title="say \"hi\""]"#)?, Vec::<i64>::new());
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_invalid_criteria() {
+        assert!(matches!(matching("[]"), Err(CriteriaError::Malformed(_))));
+        assert!(matches!(
+            matching("[title=\"open"),
+            Err(CriteriaError::Malformed(_))
+        ));
+        assert!(matches!(
+            matching("[con_id=abc]"),
+            Err(CriteriaError::Malformed(_))
+        ));
+        assert!(matches!(
+            matching("[urgent=latest]"),
+            Err(CriteriaError::Unsupported(_))
+        ));
+        assert!(matches!(
+            matching("[class=__focused__]"),
+            Err(CriteriaError::Unsupported(_))
+        ));
+        assert!(matches!(
+            matching("[class=\"(\"]"),
+            Err(CriteriaError::Regex(_))
+        ));
+    }
+}
@@ -1,4 +1,34 @@
 //! Generate a random name, using the docker algorithm
+//!
+//! Besides the docker word lists, some alternative themes are available
+use serde::Deserialize;
+
+/// Pairs of word lists to generate random names from
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Theme {
+    /// Adjectives and notable scientists, like docker
+    #[default]
+    Docker,
+    /// Shades and colors
+    Colors,
+    /// Adjectives and celestial bodies of the solar system
+    Planets,
+    /// Adjectives and greek letters
+    Greek,
+}
+
+impl Theme {
+    /// Get the left and right word lists of this theme
+    pub const fn words(self) -> (&'static [&'static str], &'static [&'static str]) {
+        match self {
+            Self::Docker => (LEFT, RIGHT),
+            Self::Colors => (SHADES, COLORS),
+            Self::Planets => (LEFT, PLANETS),
+            Self::Greek => (LEFT, GREEK_LETTERS),
+        }
+    }
+}
 
 /// Left part of random name
 static LEFT: &[&str] = &[
@@ -593,14 +623,114 @@ static RIGHT: &[&str] = &[
     "zhukovsky",
 ];
 
-// `choose` only returns none on empty arrays
-#[allow(clippy::unwrap_used)]
-/// Generate a new name
-pub fn random_name() -> String {
+/// Left part of random names in the `colors` theme
+static SHADES: &[&str] = &[
+    "bright", "cool", "dark", "deep", "dusty", "faded", "glowing", "light", "muted", "neon",
+    "pale", "pastel", "rich", "soft", "vivid", "warm",
+];
+
+/// Right part of random names in the `colors` theme
+static COLORS: &[&str] = &[
+    "amber",
+    "azure",
+    "beige",
+    "black",
+    "blue",
+    "bronze",
+    "coral",
+    "crimson",
+    "cyan",
+    "emerald",
+    "gold",
+    "gray",
+    "green",
+    "indigo",
+    "ivory",
+    "jade",
+    "lavender",
+    "lime",
+    "magenta",
+    "maroon",
+    "navy",
+    "olive",
+    "orange",
+    "peach",
+    "pink",
+    "plum",
+    "purple",
+    "red",
+    "rose",
+    "ruby",
+    "salmon",
+    "sapphire",
+    "scarlet",
+    "silver",
+    "tan",
+    "teal",
+    "turquoise",
+    "violet",
+    "white",
+    "yellow",
+];
+
+/// Right part of random names in the `planets` theme
+static PLANETS: &[&str] = &[
+    "mercury",
+    "venus",
+    "earth",
+    "mars",
+    "jupiter",
+    "saturn",
+    "uranus",
+    "neptune",
+    "pluto",
+    "ceres",
+    "eris",
+    "haumea",
+    "makemake",
+    "moon",
+    "phobos",
+    "deimos",
+    "io",
+    "europa",
+    "ganymede",
+    "callisto",
+    "titan",
+    "enceladus",
+    "mimas",
+    "rhea",
+    "iapetus",
+    "dione",
+    "tethys",
+    "miranda",
+    "ariel",
+    "umbriel",
+    "titania",
+    "oberon",
+    "triton",
+    "charon",
+    "nereid",
+];
+
+/// Right part of random names in the `greek` theme
+static GREEK_LETTERS: &[&str] = &[
+    "alpha", "beta", "gamma", "delta", "epsilon", "zeta", "eta", "theta", "iota", "kappa",
+    "lambda", "mu", "nu", "xi", "omicron", "pi", "rho", "sigma", "tau", "upsilon", "phi", "chi",
+    "psi", "omega",
+];
+
+/// Generate a new name, combining a random word from `left` and `right`
+///
+/// Empty word lists contribute an empty word
+pub fn random_name_from(
+    left: &[impl AsRef<str>],
+    right: &[impl AsRef<str>],
+    rng: &mut impl rand::Rng,
+) -> String {
     use rand::seq::SliceRandom;
     format!(
         "{}_{}",
-        LEFT.choose(&mut rand::thread_rng()).unwrap(),
-        RIGHT.choose(&mut rand::thread_rng()).unwrap()
+        left.choose(rng).map_or("", AsRef::as_ref),
+        right.choose(rng).map_or("", AsRef::as_ref)
     )
 }
@@ -0,0 +1,336 @@
+//! Duplicate the layout of a workspace onto a new workspace
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::{
+    config::Config,
+    connection::Connection,
+    insertion::{run_insertion, InsertMode, InsertionError},
+    naming::{NameSelection, NamingContext, NamingError},
+    process,
+    raw_tree::{self, RawNode, RawTreeError},
+    util::{
+        get_workspaces, run_commands, CommandError, ContainerChanges, InsertionDestination, Pivot,
+    },
+};
+
+/// Errors while duplicating a workspace
+#[derive(Debug, Error)]
+pub enum DuplicateError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Could not find workspace \"{0}\"")]
+    NoWorkspace(String),
+    #[error("Duplicating needs append_layout, which sway does not support")]
+    Unsupported,
+    #[error("Could not write layout file: {0}")]
+    WriteLayout(std::io::Error),
+    #[error("Could not name workspace: {0}")]
+    Naming(#[from] NamingError),
+    #[error("{0}")]
+    Insertion(#[from] InsertionError),
+    #[error("Could not restore layout: {0}")]
+    Command(#[from] CommandError),
+}
+
+/// Options of the `duplicate` subcommand
+#[derive(clap::Args, Debug)]
+pub struct DuplicateArgs {
+    /// Workspace whose layout is duplicated, defaults to the focused workspace
+    #[clap(short, long)]
+    workspace: Option<String>,
+
+    /// Start the programs of the duplicated windows again, instead of only leaving placeholders
+    #[clap(long)]
+    exec: bool,
+
+    #[clap(flatten)]
+    name: NameSelection,
+}
+
+impl DuplicateArgs {
+    /// Insert a new workspace after the duplicated one, and append the layout of the duplicated one to it
+    ///
+    /// Uses `append_layout`, which only i3 supports, so it fails before inserting for sway
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        config: &Config,
+        mode: &InsertMode,
+        focused_workspace: &str,
+    ) -> Result<(), DuplicateError> {
+        if let InsertMode::Sway = mode {
+            return Err(DuplicateError::Unsupported);
+        }
+        let source = self
+            .workspace
+            .unwrap_or_else(|| focused_workspace.to_owned());
+        let output = get_workspaces(conn)?
+            .into_iter()
+            .find(|x| x.name == source)
+            .ok_or_else(|| DuplicateError::NoWorkspace(source.clone()))?
+            .output;
+        let tree = raw_tree::get_tree(conn)?;
+        let workspace = find_workspace(&tree, &source)
+            .ok_or_else(|| DuplicateError::NoWorkspace(source.clone()))?;
+
+        let pivot = Pivot {
+            name: source.clone(),
+            output: Some(output),
+        };
+        let context = NamingContext {
+            pivot: &pivot,
+            before: false,
+            container: None,
+            inserted: &[],
+        };
+        let name = self.name.resolve(conn, config, &context)?;
+        let destination = InsertionDestination::new(pivot, false);
+        run_insertion(
+            conn,
+            mode,
+            &destination,
+            std::slice::from_ref(&name),
+            &[],
+            ContainerChanges::default(),
+            &[],
+        )?;
+
+        // `append_layout` expects one JSON object per top level container
+        let layout = workspace
+            .children()
+            .map(|x| layout_json(x).to_string())
+            .collect::<Vec<_>>()
+            .join("\n");
+        let path = std::env::temp_dir().join(format!(
+            "i3-insert-workspace-layout-{}.json",
+            std::process::id()
+        ));
+        std::fs::write(&path, layout).map_err(DuplicateError::WriteLayout)?;
+        let mut commands = vec![
+            format!("workspace \"{name}\""),
+            format!("append_layout {}", path.display()),
+        ];
+        if self.exec {
+            commands.extend(exec_commands(workspace));
+        }
+        let result = run_commands(conn, &commands);
+        // The layout is read while the command runs, so the file is no longer needed
+        std::fs::remove_file(&path).map_err(DuplicateError::WriteLayout)?;
+        Ok(result?)
+    }
+}
+
+/// Find the workspace node named `name` below `node`
+fn find_workspace<'a>(node: &'a RawNode, name: &str) -> Option<&'a RawNode> {
+    if node.nodetype == "workspace" && node.name.as_deref() == Some(name) {
+        return Some(node);
+    }
+    node.nodes.iter().find_map(|x| find_workspace(x, name))
+}
+
+/// Check if `node` holds a window, instead of other containers
+fn is_window(node: &RawNode) -> bool {
+    node.window.is_some() || node.app_id.is_some()
+}
+
+/// Criteria matching windows like the window of `node`, for the placeholder replacing it
+fn swallow_criteria(node: &RawNode) -> Map<String, Value> {
+    let exact = |value: &str| Value::from(format!("^{}$", regex::escape(value)));
+    let mut criteria = Map::new();
+    let properties = node.window_properties.as_ref();
+    if let Some(class) = properties.and_then(|x| x.class.as_deref()) {
+        criteria.insert("class".to_owned(), exact(class));
+    }
+    if let Some(instance) = properties.and_then(|x| x.instance.as_deref()) {
+        criteria.insert("instance".to_owned(), exact(instance));
+    }
+    if criteria.is_empty() {
+        if let Some(title) = &node.name {
+            criteria.insert("title".to_owned(), exact(title));
+        }
+    }
+    criteria
+}
+
+/// Describe the subtree of `node` in the format of `append_layout`, with placeholders instead of windows
+fn layout_json(node: &RawNode) -> Value {
+    if is_window(node) {
+        return json!({
+            "type": "con",
+            "percent": node.percent,
+            "name": node.name,
+            "swallows": [swallow_criteria(node)],
+        });
+    }
+    let nodes = node.nodes.iter().map(layout_json).collect::<Vec<_>>();
+    if node.nodetype == "floating_con" {
+        return json!({
+            "type": "floating_con",
+            "nodes": nodes,
+        });
+    }
+    json!({
+        "type": "con",
+        "layout": node.layout,
+        "percent": node.percent,
+        "nodes": nodes,
+    })
+}
+
+/// Commands starting the programs of the windows below `node` again
+fn exec_commands(node: &RawNode) -> Vec<String> {
+    if is_window(node) {
+        return process::window_pid(node)
+            .and_then(process::command_line)
+            .map(|arguments| {
+                let shell_command = arguments
+                    .iter()
+                    .map(|x| format!("'{}'", x.replace('\'', "'\\''")))
+                    .collect::<Vec<_>>()
+                    .join(" ");
+                format!(
+                    "exec --no-startup-id \"{}\"",
+                    shell_command.replace('\\', "\\\\").replace('"', "\\\"")
+                )
+            })
+            .into_iter()
+            .collect();
+    }
+    node.children().flat_map(exec_commands).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::{exec_commands, find_workspace, layout_json, swallow_criteria};
+    use crate::raw_tree::{RawNode, WindowProperties};
+
+    fn window(id: i64, class: Option<&str>, title: &str) -> RawNode {
+        RawNode {
+            id,
+            name: Some(title.to_owned()),
+            nodetype: "con".to_owned(),
+            percent: Some(0.5),
+            window: u64::try_from(id).ok(),
+            window_properties: Some(WindowProperties {
+                class: class.map(ToOwned::to_owned),
+                instance: class.map(str::to_lowercase),
+                ..WindowProperties::default()
+            }),
+            ..RawNode::default()
+        }
+    }
+
+    fn workspace(name: &str, nodes: Vec<RawNode>) -> RawNode {
+        RawNode {
+            name: Some(name.to_owned()),
+            nodetype: "workspace".to_owned(),
+            nodes,
+            ..RawNode::default()
+        }
+    }
+
+    #[test]
+    fn matches_windows_exactly() {
+        let criteria = swallow_criteria(&window(1, Some("Foo.Bar"), "title"));
+        assert_eq!(
+            serde_json::Value::from(criteria),
+            json!({"class": "^Foo\\.Bar$", "instance": "^foo\\.bar$"})
+        );
+        let criteria = swallow_criteria(&window(1, None, "notes (1)"));
+        assert_eq!(
+            serde_json::Value::from(criteria),
+            json!({"title": "^notes \\(1\\)$"})
+        );
+    }
+
+    #[test]
+    fn replaces_windows_by_placeholders() {
+        let split = RawNode {
+            nodetype: "con".to_owned(),
+            layout: "tabbed".to_owned(),
+            percent: Some(1.0),
+            nodes: vec![window(1, Some("Firefox"), "web"), window(2, None, "notes")],
+            ..RawNode::default()
+        };
+        assert_eq!(
+            layout_json(&split),
+            json!({
+                "type": "con",
+                "layout": "tabbed",
+                "percent": 1.0,
+                "nodes": [
+                    {
+                        "type": "con",
+                        "percent": 0.5,
+                        "name": "web",
+                        "swallows": [{"class": "^Firefox$", "instance": "^firefox$"}],
+                    },
+                    {
+                        "type": "con",
+                        "percent": 0.5,
+                        "name": "notes",
+                        "swallows": [{"title": "^notes$"}],
+                    },
+                ],
+            })
+        );
+    }
+
+    #[test]
+    fn keeps_floating_containers() {
+        let floating = RawNode {
+            nodetype: "floating_con".to_owned(),
+            nodes: vec![window(1, None, "notes")],
+            ..RawNode::default()
+        };
+        assert_eq!(
+            layout_json(&floating),
+            json!({
+                "type": "floating_con",
+                "nodes": [{
+                    "type": "con",
+                    "percent": 0.5,
+                    "name": "notes",
+                    "swallows": [{"title": "^notes$"}],
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn finds_workspaces_by_name() {
+        let tree = RawNode {
+            nodetype: "root".to_owned(),
+            nodes: vec![RawNode {
+                nodetype: "output".to_owned(),
+                nodes: vec![workspace("1", Vec::new()), workspace("2", Vec::new())],
+                ..RawNode::default()
+            }],
+            ..RawNode::default()
+        };
+        assert_eq!(
+            find_workspace(&tree, "2").and_then(|x| x.name.as_deref()),
+            Some("2")
+        );
+        assert!(find_workspace(&tree, "3").is_none());
+    }
+
+    #[test]
+    fn restarts_programs_by_their_command_line() {
+        let program = RawNode {
+            pid: Some(std::process::id()),
+            app_id: Some("test".to_owned()),
+            ..window(1, None, "test")
+        };
+        let commands = exec_commands(&workspace("1", vec![program]));
+        let [command] = commands.as_slice() else {
+            panic!("expected one command, got {commands:?}");
+        };
+        assert!(command.starts_with("exec --no-startup-id \"'"), "{command}");
+    }
+}
@@ -1,63 +1,290 @@
+use i3ipc::reply::{CommandOutcome, Workspace};
 use thiserror::Error;
 
-use crate::util::InsertionDestination;
+use crate::{
+    backend::{BackendError, WmBackend},
+    plan::Plan,
+    util::{renamed, ContainerChanges, InsertionDestination, WorkspaceRename},
+};
 
 /// Errors for `insert_workspace`
 #[derive(Debug, Error)]
 pub enum InsertionError {
     #[error("Could not find workspace \"{0}\"")]
     NoPivotWorkspace(String),
-    #[error("i3 IPC error message: \"{0}\"")]
-    CommandError(
-        #[from]
-        #[source]
-        i3ipc::MessageError,
-    ),
+    #[error("i3 IPC command error: \"{0}\"")]
+    CommandError(String),
+    #[error("{0}")]
+    Backend(#[from] BackendError),
 }
 
-/// Insert a new workspace at the given location
-#[allow(clippy::indexing_slicing)]
+/// Insert new workspaces named `names` at the given location, in this order
+///
+/// `containers` are moved to the first new workspace, after making `changes` to them.
+/// `renames` are applied before the insertion
 pub fn insert_workspace(
-    conn: &mut i3ipc::I3Connection,
+    backend: &mut impl WmBackend,
     insertion_marker: &InsertionDestination,
-    name: &str,
-    container: Option<i64>,
-) -> Result<(), InsertionError> {
-    let t = conn.get_workspaces()?;
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    let workspaces = backend.get_workspaces()?;
+    let plan = insertion_plan(
+        &workspaces,
+        insertion_marker,
+        names,
+        containers,
+        changes,
+        renames,
+    )?;
+    check_outcomes(&plan.run(backend)?)?;
+    Ok(plan)
+}
+
+/// Fail with the error of the first failed command in `outcomes`
+pub fn check_outcomes(outcomes: &[CommandOutcome]) -> Result<(), InsertionError> {
+    let errored_command = outcomes.iter().find(|x| !x.success);
+    if let Some(ec) = errored_command {
+        return Err(InsertionError::CommandError(
+            ec.error
+                .clone()
+                .unwrap_or_else(|| "No error message, but errored".to_string()),
+        ));
+    }
+    Ok(())
+}
 
-    let pivot_id = t
-        .workspaces
+/// Plan the insertion of new workspaces named `names` at the given location between `workspaces`
+///
+/// Every workspace following the new ones is renamed to its own name, moving it to the end of its output
+#[allow(clippy::indexing_slicing)]
+pub fn insertion_plan(
+    workspaces: &[Workspace],
+    insertion_marker: &InsertionDestination,
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    let pivot_id = workspaces
         .iter()
-        .position(|x| x.name == insertion_marker.pivot())
+        .position(|x| {
+            x.name == insertion_marker.pivot()
+                && insertion_marker
+                    .pivot_output()
+                    .is_none_or(|output| x.output == output)
+        })
         .ok_or_else(|| InsertionError::NoPivotWorkspace(insertion_marker.pivot().to_owned()))?;
 
-    let output = &t.workspaces[pivot_id].output;
+    let output = &workspaces[pivot_id].output;
 
-    let stop_id = t.workspaces[pivot_id..]
+    let stop_id = workspaces[pivot_id..]
         .iter()
         .position(|x| &x.output != output)
-        .map_or(t.workspaces.len(), |x| x + pivot_id);
+        .map_or(workspaces.len(), |x| x + pivot_id);
 
     let start_id = match insertion_marker {
         InsertionDestination::After { .. } => pivot_id + 1,
         InsertionDestination::Before { .. } => pivot_id,
     };
 
-    // Renaming moves the workspace to the end of list of workspaces in the output
-    let rename_commands: Vec<_> = t.workspaces[start_id..stop_id]
+    // Moving containers creates their workspace on the focused output, which is switched for the insertion
+    let origin = workspaces
         .iter()
-        .filter(|x| x.name != name)
-        .map(|x| format!("rename workspace \"{0}\" to \"{0}\";", x.name.clone()))
-        .collect();
+        .find(|x| x.focused)
+        .filter(|x| !containers.is_empty() && &x.output != output);
 
-    let creation_command = container.map_or_else(
-        || format!("workspace \"{name}\""),
-        |container_id| format!("[con_id={container_id}] move container to workspace {name}"),
+    let mut commands = renames
+        .iter()
+        .map(WorkspaceRename::command)
+        .collect::<Vec<_>>();
+    if origin.is_some() {
+        commands.push(format!("focus output \"{output}\""));
+    }
+    for (index, name) in names.iter().enumerate() {
+        if index == 0 && !containers.is_empty() {
+            commands.extend(changes.commands(containers));
+            commands.extend(containers.iter().map(|container_id| {
+                format!("[con_id={container_id}] move container to workspace {name}")
+            }));
+        } else {
+            commands.push(format!("workspace \"{name}\""));
+            commands.push(format!("move workspace to output {output}"));
+        }
+    }
+    // Renaming moves the workspace to the end of list of workspaces in the output
+    commands.extend(
+        workspaces[start_id..stop_id]
+            .iter()
+            .filter(|x| !names.contains(&x.name))
+            .map(|x| {
+                format!(
+                    "rename workspace \"{0}\" to \"{0}\"",
+                    renamed(renames, &x.name)
+                )
+            }),
     );
+    commands.extend(origin.map(|x| format!("workspace \"{}\"", renamed(renames, &x.name))));
+    let on_output = |x: &&Workspace| &x.output == output;
+    Ok(Plan::new(
+        commands,
+        output,
+        workspaces.iter().filter(on_output).map(|x| x.name.as_str()),
+        workspaces[..start_id].iter().filter(on_output).count(),
+        names,
+        renames,
+    ))
+}
 
-    conn.run_command(&format!(
-        "{creation_command}; move workspace to output {output}; {}",
-        rename_commands.join("")
-    ))?;
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::{insert_workspace, insertion_plan, InsertionError};
+    use crate::{
+        backend::WmBackend,
+        mock::MockBackend,
+        plan::Plan,
+        util::{ContainerChanges, InsertionDestination, Pivot},
+    };
+
+    fn insert(
+        backend: &mut MockBackend,
+        pivot: &str,
+        before: bool,
+        names: &[&str],
+    ) -> Result<Plan, InsertionError> {
+        let pivot = Pivot {
+            name: pivot.to_owned(),
+            output: None,
+        };
+        let names = names.iter().map(|x| (*x).to_owned()).collect::<Vec<_>>();
+        insert_workspace(
+            backend,
+            &InsertionDestination::new(pivot, before),
+            &names,
+            &[],
+            ContainerChanges::default(),
+            &[],
+        )
+    }
+
+    fn backend() -> MockBackend {
+        MockBackend::with_outputs(&[("A", &["a", "b", "c"]), ("B", &["d", "e"])])
+    }
+
+    #[test]
+    fn after_pivot() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "b", false, &["new"])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "new", "c"]);
+        assert_eq!(backend.workspace_names("B"), ["d", "e"]);
+        assert_eq!(backend.focused(), Some("new"));
+        Ok(())
+    }
+
+    #[test]
+    fn before_pivot() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "b", true, &["new"])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "new", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn before_first() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "a", true, &["new"])?;
+        assert_eq!(backend.workspace_names("A"), ["new", "a", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn after_last() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "c", false, &["new"])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "c", "new"]);
+        Ok(())
+    }
+
+    #[test]
+    fn on_other_output() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "d", false, &["new"])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "c"]);
+        assert_eq!(backend.workspace_names("B"), ["d", "new", "e"]);
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_names() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "a", false, &["x", "y", "z"])?;
+        assert_eq!(backend.focused(), Some("z"));
+        // Empty workspaces vanish once they lose focus, only the last new one remains
+        assert_eq!(backend.workspace_names("A"), ["a", "z", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn missing_pivot() {
+        let mut backend = backend();
+        assert!(matches!(
+            insert(&mut backend, "f", false, &["new"]),
+            Err(InsertionError::NoPivotWorkspace(name)) if name == "f"
+        ));
+        assert!(backend.commands().is_empty());
+    }
+
+    #[test]
+    fn rejected_command() {
+        let mut backend = backend();
+        let pivot = Pivot {
+            name: "a".to_owned(),
+            output: None,
+        };
+        // Container 7 does not exist, so moving it fails
+        let result = insert_workspace(
+            &mut backend,
+            &InsertionDestination::new(pivot, false),
+            &["new".to_owned()],
+            &[7],
+            ContainerChanges::default(),
+            &[],
+        );
+        assert!(matches!(result, Err(InsertionError::CommandError(_))));
+    }
+
+    #[test]
+    fn changes_containers_before_moving_them() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        let pivot = Pivot {
+            name: "b".to_owned(),
+            output: None,
+        };
+        let plan = insertion_plan(
+            &backend.get_workspaces()?,
+            &InsertionDestination::new(pivot, false),
+            &["new".to_owned()],
+            &[1],
+            ContainerChanges {
+                mark: Some("moved"),
+                unstick: &[1],
+            },
+            &[],
+        )?;
+        assert_eq!(
+            plan.commands.get(..3),
+            Some(
+                [
+                    "[con_id=1] sticky disable",
+                    "[con_id=1] mark --add \"moved\"",
+                    "[con_id=1] move container to workspace new",
+                ]
+                .map(str::to_owned)
+                .as_slice()
+            )
+        );
+        Ok(())
+    }
 }
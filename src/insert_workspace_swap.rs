@@ -1,55 +1,65 @@
-use i3ipc::reply::Node;
+use i3ipc::reply::CommandOutcome;
 use thiserror::Error;
 
-use crate::util::InsertionDestination;
+use crate::{
+    backend::{BackendError, WmBackend},
+    plan::Plan,
+    raw_tree::RawNode,
+    util::{renamed, ContainerChanges, InsertionDestination, WorkspaceRename, SCRATCHPAD_OUTPUT},
+};
 
 /// Errors for `insert_workspace`
 #[derive(Debug, Error)]
 pub enum InsertionError {
     #[error("Could not find workspace \"{0}\"")]
     NoPivotWorkspace(String),
-    #[error("i3 IPC connection error: \"{0}\"")]
-    ConnectionError(
-        #[from]
-        #[source]
-        i3ipc::MessageError,
-    ),
     #[error("i3 IPC command error: \"{0}\"")]
     CommandError(String),
+    #[error("{0}")]
+    Backend(#[from] BackendError),
 }
 
 /// Finds the output containing the workspace named `workspace_name`
 ///
+/// If `output_name` is given, only this output is searched.
+/// The internal `__i3` output holding the scratchpad is skipped, as are nodes which are no outputs or workspaces.
+///
 /// Returns the `Output` node, and the index of the workspace in this output node
 fn find_workspaces_output<'a>(
-    root_node: &'a Node,
+    root_node: &'a RawNode,
     workspace_name: &'_ str,
-) -> Option<(&'a Node, usize)> {
-    assert_eq!(root_node.nodetype, i3ipc::reply::NodeType::Root);
-
-    root_node.nodes.iter().find_map(|output_node| {
-        assert_eq!(output_node.nodetype, i3ipc::reply::NodeType::Output);
-        output_node
-            .nodes
-            .iter()
-            .position(|x| {
-                assert_eq!(x.nodetype, i3ipc::reply::NodeType::Workspace);
-                x.name.as_ref().map_or(false, |wn| wn == workspace_name)
-            })
-            .map(|workspace_index| (output_node, workspace_index))
-    })
+    output_name: Option<&'_ str>,
+) -> Option<(&'a RawNode, usize)> {
+    root_node
+        .nodes
+        .iter()
+        .filter(|x| x.nodetype == "output")
+        .filter(|x| x.name.as_deref() != Some(SCRATCHPAD_OUTPUT))
+        .find_map(|output_node| {
+            if output_name.is_some_and(|on| output_node.name.as_deref() != Some(on)) {
+                return None;
+            }
+            output_node
+                .nodes
+                .iter()
+                .position(|x| {
+                    x.nodetype == "workspace"
+                        && x.name.as_ref().is_some_and(|wn| wn == workspace_name)
+                })
+                .map(|workspace_index| (output_node, workspace_index))
+        })
 }
 
-fn get_child_node_by_id(node: &Node, id: i64) -> Option<&Node> {
+fn get_child_node_by_id(node: &RawNode, id: i64) -> Option<&RawNode> {
     node.nodes.iter().find(|x| x.id == id)
 }
 
-fn get_floating_child_node_by_id(node: &Node, id: i64) -> Option<&Node> {
+fn get_floating_child_node_by_id(node: &RawNode, id: i64) -> Option<&RawNode> {
     node.floating_nodes.iter().find(|x| x.id == id)
 }
 
 /// Check if `ws` is focused, or one of its children is focused
-fn is_focused(ws: &Node) -> bool {
+fn is_focused(ws: &RawNode) -> bool {
     if ws.focused {
         return true;
     }
@@ -63,17 +73,27 @@ fn is_focused(ws: &Node) -> bool {
             get_child_node_by_id(ws, *next_focus_id)
                 .or_else(|| get_floating_child_node_by_id(ws, *next_focus_id))
         })
-        .map_or(false, is_focused)
+        .is_some_and(is_focused)
 }
 
-fn move_workspace_to_end(source: &Node, container: Option<i64>) -> Vec<String> {
+/// Move the containers of `source` to the end of its output, through a dummy workspace
+///
+/// Top level containers are moved as a whole, keeping the layout of the containers inside them.
+/// `renames` determine the name of the workspace after the move.
+/// If `shown`, the dummy workspace is shown instead of `source`, as a shown workspace is not destroyed once empty
+fn move_workspace_to_end(
+    source: &RawNode,
+    containers: &[i64],
+    renames: &[WorkspaceRename],
+    shown: bool,
+) -> Vec<String> {
     let dummy_name = format!("dummy_workspace_{:#?}", std::ptr::addr_of!(source));
 
     let mut movings = source
         .nodes
         .iter()
         .chain(source.floating_nodes.iter())
-        .filter(|x| container.map_or(true, |cid| x.id != cid))
+        .filter(|x| !containers.contains(&x.id))
         .map(|container| {
             format!(
                 "[con_id={conid}] move container to workspace {dummy_name}",
@@ -81,64 +101,512 @@ fn move_workspace_to_end(source: &Node, container: Option<i64>) -> Vec<String> {
             )
         })
         .collect::<Vec<_>>();
-    // If we move the container somewhere, we want to stay in the current workspace
-    // But this workspace should be shifted none the less
-    if container.is_some() && is_focused(source) {
+    if shown {
         movings.push(format!("workspace {dummy_name}"));
     }
     if !movings.is_empty() {
         #[allow(clippy::expect_used)]
         movings.push(format!(
             "rename workspace {dummy_name} to {conname}",
-            conname = source.name.as_ref().expect("Workspace did not have a name")
+            conname = renamed(
+                renames,
+                source.name.as_ref().expect("Workspace did not have a name")
+            )
         ));
     }
     movings
 }
 
-/// Insert a new workspace at the given location
+/// Commands restoring the position and size of the floating containers of `workspace`
+///
+/// Moving a floating container to another workspace resets its geometry,
+/// so these are run after the workspace has been shuffled
+fn restore_floating_geometry(workspace: &RawNode, containers: &[i64]) -> Vec<String> {
+    workspace
+        .floating_nodes
+        .iter()
+        .filter(|x| !containers.contains(&x.id))
+        .flat_map(|x| {
+            [
+                format!(
+                    "[con_id={}] resize set {} px {} px",
+                    x.id, x.rect.width, x.rect.height
+                ),
+                format!(
+                    "[con_id={}] move absolute position {} px {} px",
+                    x.id, x.rect.x, x.rect.y
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// Commands restoring the fullscreen state of the containers in `workspace`
+///
+/// Containers lose their fullscreen state when their workspace is shuffled
+fn restore_fullscreen(workspace: &RawNode) -> Vec<String> {
+    workspace
+        .descendants()
+        .into_iter()
+        .filter_map(|x| match x.fullscreen_mode? {
+            1 => Some(format!("[con_id={}] fullscreen enable", x.id)),
+            2 => Some(format!("[con_id={}] fullscreen enable global", x.id)),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Command restoring the layout of `workspace` itself, lost to the default layout of the dummy workspace
+fn restore_layout(workspace: &RawNode, containers: &[i64]) -> Option<String> {
+    let layout = match workspace.layout.as_str() {
+        "splith" => "splith",
+        "splitv" => "splitv",
+        "stacked" => "stacking",
+        "tabbed" => "tabbed",
+        _ => return None,
+    };
+    // Changing the layout of a container changes the layout of its parent
+    let child = workspace
+        .nodes
+        .iter()
+        .find(|x| !containers.contains(&x.id))?;
+    Some(format!("[con_id={}] layout {layout}", child.id))
+}
+
+/// Get the windows below `node` from the most to the least recently focused, skipping `containers`
+fn focus_order(node: &RawNode, containers: &[i64]) -> Vec<i64> {
+    if node.nodes.is_empty() && node.floating_nodes.is_empty() {
+        return vec![node.id];
+    }
+    node.focus
+        .iter()
+        .filter(|id| !containers.contains(id))
+        .filter_map(|id| {
+            get_child_node_by_id(node, *id).or_else(|| get_floating_child_node_by_id(node, *id))
+        })
+        .flat_map(|x| focus_order(x, containers))
+        .collect()
+}
+
+/// Commands restoring the focus order inside `workspace`, by focusing its windows from the least recently focused on
+fn restore_focus_order(workspace: &RawNode, containers: &[i64]) -> Vec<String> {
+    focus_order(workspace, containers)
+        .into_iter()
+        .rev()
+        .map(|id| format!("[con_id={id}] focus"))
+        .collect()
+}
+
+/// Commands restoring the focus order inside the `moved` workspaces, and focusing `origin` again afterwards
+///
+/// Focusing windows switches workspaces, which destroys empty workspaces,
+/// so the focus order is only restored if `containers` were moved to the new workspace,
+/// and not all of them from the focused workspace.
+/// The focused workspace is restored last
+fn restore_focus<'a>(
+    moved: impl Iterator<Item = &'a RawNode>,
+    origin: Option<&RawNode>,
+    containers: &[i64],
+    renames: &[WorkspaceRename],
+    other_output: bool,
+) -> Vec<String> {
+    if containers.is_empty() {
+        return Vec::new();
+    }
+    let origin_emptied = origin.is_none_or(|x| {
+        x.nodes
+            .iter()
+            .chain(x.floating_nodes.iter())
+            .all(|x| containers.contains(&x.id))
+    });
+    let mut commands = Vec::new();
+    if !origin_emptied {
+        let (focused, unfocused): (Vec<_>, Vec<_>) = moved.partition(|x| is_focused(x));
+        commands.extend(
+            unfocused
+                .iter()
+                .chain(focused.iter())
+                .flat_map(|x| restore_focus_order(x, containers)),
+        );
+    }
+    if !commands.is_empty() || other_output {
+        commands.extend(
+            origin
+                .and_then(|x| x.name.as_deref())
+                .map(|x| format!("workspace \"{}\"", renamed(renames, x))),
+        );
+    }
+    commands
+}
+
+/// Commands restoring the urgency hints in `workspace`
+///
+/// Focusing a window clears its urgency hint, so these are run after the focus order is restored.
+/// Marks need no restoring, as they move with their containers
+fn restore_urgency(workspace: &RawNode, urgent: &[i64]) -> Vec<String> {
+    workspace
+        .descendants()
+        .into_iter()
+        .filter(|x| urgent.contains(&x.id))
+        .map(|x| format!("[con_id={}] urgent enable", x.id))
+        .collect()
+}
+
+/// Get the ids of the urgent containers below `node`
+fn urgent_containers(node: &RawNode, ids: &mut Vec<i64>) {
+    if node.urgent {
+        ids.push(node.id);
+    }
+    for child in node.nodes.iter().chain(node.floating_nodes.iter()) {
+        urgent_containers(child, ids);
+    }
+}
+
+/// Insert new workspaces named `names` at the given location, in this order
+///
+/// `containers` are moved to the first new workspace, after making `changes` to them.
+/// `renames` are applied before the insertion
 pub fn insert_workspace(
-    conn: &mut i3ipc::I3Connection,
+    backend: &mut impl WmBackend,
     insertion_marker: &InsertionDestination,
-    name: &str,
-    container: Option<i64>,
-) -> Result<(), InsertionError> {
-    let root_node = conn.get_tree()?;
-    let (output_node, workspace_id) = find_workspaces_output(&root_node, insertion_marker.pivot())
-        .ok_or_else(|| InsertionError::NoPivotWorkspace(insertion_marker.pivot().to_owned()))?;
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    let root_node = backend.get_tree()?;
+    let plan = insertion_plan(
+        &root_node,
+        insertion_marker,
+        names,
+        containers,
+        changes,
+        renames,
+    )?;
+    check_outcomes(&plan.run(backend)?)?;
+    Ok(plan)
+}
+
+/// Fail with the error of the first failed command in `outcomes`
+pub fn check_outcomes(outcomes: &[CommandOutcome]) -> Result<(), InsertionError> {
+    let errored_command = outcomes.iter().find(|x| !x.success);
+    if let Some(ec) = errored_command {
+        return Err(InsertionError::CommandError(
+            ec.error
+                .clone()
+                .unwrap_or_else(|| "No error message, but errored".to_string()),
+        ));
+    }
+    Ok(())
+}
+
+/// Plan the insertion of new workspaces named `names` at the given location into the tree `root_node`
+///
+/// Every workspace following the new ones is emptied into a dummy workspace, which then takes its name,
+/// moving it to the end of its output
+pub fn insertion_plan(
+    root_node: &RawNode,
+    insertion_marker: &InsertionDestination,
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    let (output_node, workspace_id) = find_workspaces_output(
+        root_node,
+        insertion_marker.pivot(),
+        insertion_marker.pivot_output(),
+    )
+    .ok_or_else(|| InsertionError::NoPivotWorkspace(insertion_marker.pivot().to_owned()))?;
 
     let first_moved_workspace = match insertion_marker {
         InsertionDestination::After { .. } => workspace_id + 1,
         InsertionDestination::Before { .. } => workspace_id,
     };
 
-    // Move to workspace {name}
+    // Move to workspaces {names}
     // Move everything from first-to-move ($a) to new dummy workspace
     // Rename dummy workspace to $a after $a it has been emptied
 
-    let initial_workspace_command = container.map_or_else(
-        || format!("workspace {name}"),
-        |conid| format!("[con_id={conid}] move container to workspace {name}"),
-    );
-    let mut commands = vec![initial_workspace_command];
+    let origin = root_node
+        .nodes
+        .iter()
+        .filter(|x| x.name.as_deref() != Some(SCRATCHPAD_OUTPUT))
+        .flat_map(|x| x.nodes.iter())
+        .find(|x| is_focused(x));
+    // New workspaces are created on the focused output, so the output of the pivot is focused first
+    let other_output = origin.is_some_and(|x| {
+        !output_node
+            .nodes
+            .iter()
+            .any(|workspace| workspace.id == x.id)
+    });
 
-    let new_commands = output_node
+    let mut commands = renames
+        .iter()
+        .map(WorkspaceRename::command)
+        .collect::<Vec<_>>();
+    if other_output {
+        commands.push(format!(
+            "focus output \"{}\"",
+            output_node.name.as_deref().unwrap_or_default()
+        ));
+    }
+    for (index, name) in names.iter().enumerate() {
+        if index == 0 && !containers.is_empty() {
+            commands.extend(changes.commands(containers));
+            commands.extend(
+                containers
+                    .iter()
+                    .map(|conid| format!("[con_id={conid}] move container to workspace {name}")),
+            );
+        } else {
+            commands.push(format!("workspace {name}"));
+        }
+    }
+
+    let moved_workspaces = output_node
         .nodes
         .iter()
         .skip(first_moved_workspace)
-        .flat_map(|x| move_workspace_to_end(x, container).into_iter());
+        .filter(|x| x.nodetype == "workspace");
+    // The focus moves to the pivot's output if it was elsewhere
+    let shown = if other_output {
+        output_node.focus.first().copied()
+    } else {
+        origin.map(|x| x.id)
+    };
+    // If we move containers somewhere, we want to stay in the current workspace
+    // But this workspace should be shifted none the less
+    let new_commands = moved_workspaces.clone().flat_map(|x| {
+        let shown = !containers.is_empty() && shown == Some(x.id);
+        move_workspace_to_end(x, containers, renames, shown).into_iter()
+    });
     commands.extend(new_commands);
+    commands.extend(
+        moved_workspaces
+            .clone()
+            .flat_map(|x| restore_floating_geometry(x, containers)),
+    );
+    for workspace in moved_workspaces.clone() {
+        commands.extend(restore_layout(workspace, containers));
+        commands.extend(restore_fullscreen(workspace));
+    }
 
-    let joined_commands = commands.join("; ");
-    let replies = &conn.run_command(&joined_commands)?;
+    commands.extend(restore_focus(
+        moved_workspaces.clone(),
+        origin,
+        containers,
+        renames,
+        other_output,
+    ));
 
-    let errored_command = replies.outcomes.iter().find(|x| !x.success);
-    if let Some(ec) = errored_command {
-        return Err(InsertionError::CommandError(
-            ec.error
-                .clone()
-                .unwrap_or_else(|| "No error message, but errored".to_string()),
+    let mut urgent = Vec::new();
+    for workspace in moved_workspaces.clone() {
+        urgent_containers(workspace, &mut urgent);
+    }
+    for workspace in moved_workspaces {
+        commands.extend(restore_urgency(workspace, &urgent));
+    }
+
+    let is_workspace = |x: &&RawNode| x.nodetype == "workspace";
+    Ok(Plan::new(
+        commands,
+        output_node.name.as_deref().unwrap_or_default(),
+        output_node
+            .nodes
+            .iter()
+            .filter(is_workspace)
+            .filter_map(|x| x.name.as_deref()),
+        output_node
+            .nodes
+            .iter()
+            .take(first_moved_workspace)
+            .filter(is_workspace)
+            .count(),
+        names,
+        renames,
+    ))
+}
+
+/// Commands moving the workspaces named `moved` on `output` to its end, in this order, through dummy workspaces
+///
+/// Unlike i3, sway keeps the position of a workspace renamed to its own name.
+/// The focus returns to the focused workspace afterwards
+pub fn reorder_commands(root_node: &RawNode, output: &str, moved: &[&str]) -> Vec<String> {
+    let Some((output_node, _)) = moved
+        .first()
+        .and_then(|x| find_workspaces_output(root_node, x, Some(output)))
+    else {
+        return Vec::new();
+    };
+    let moved_workspaces = moved
+        .iter()
+        .filter_map(|name| {
+            output_node
+                .nodes
+                .iter()
+                .find(|x| x.nodetype == "workspace" && x.name.as_deref() == Some(name))
+        })
+        .collect::<Vec<_>>();
+    let origin = root_node
+        .nodes
+        .iter()
+        .filter(|x| x.name.as_deref() != Some(SCRATCHPAD_OUTPUT))
+        .flat_map(|x| x.nodes.iter())
+        .find(|x| is_focused(x));
+    let other_output = origin.is_some_and(|x| !output_node.nodes.iter().any(|y| y.id == x.id));
+
+    // Dummy workspaces are created on the focused output
+    let mut commands = Vec::new();
+    if other_output {
+        commands.push(format!("focus output \"{output}\""));
+    }
+    let shown = output_node.focus.first().copied();
+    for workspace in &moved_workspaces {
+        commands.extend(move_workspace_to_end(
+            workspace,
+            &[],
+            &[],
+            shown == Some(workspace.id),
         ));
     }
-    Ok(())
+    let mut urgent = Vec::new();
+    for workspace in &moved_workspaces {
+        commands.extend(restore_floating_geometry(workspace, &[]));
+        commands.extend(restore_layout(workspace, &[]));
+        commands.extend(restore_fullscreen(workspace));
+        urgent_containers(workspace, &mut urgent);
+    }
+    for workspace in &moved_workspaces {
+        commands.extend(restore_urgency(workspace, &urgent));
+    }
+    if other_output {
+        commands.extend(
+            origin
+                .and_then(|x| x.name.as_deref())
+                .map(|x| format!("workspace \"{x}\"")),
+        );
+    }
+    commands
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{insert_workspace, InsertionError};
+    use crate::{
+        mock::MockBackend,
+        plan::Plan,
+        util::{ContainerChanges, InsertionDestination, Pivot},
+    };
+
+    fn insert(
+        backend: &mut MockBackend,
+        pivot: &str,
+        before: bool,
+        names: &[&str],
+        containers: &[i64],
+    ) -> Result<Plan, InsertionError> {
+        let pivot = Pivot {
+            name: pivot.to_owned(),
+            output: None,
+        };
+        let names = names.iter().map(|x| (*x).to_owned()).collect::<Vec<_>>();
+        insert_workspace(
+            backend,
+            &InsertionDestination::new(pivot, before),
+            &names,
+            containers,
+            ContainerChanges::default(),
+            &[],
+        )
+    }
+
+    fn backend() -> MockBackend {
+        MockBackend::with_outputs(&[("A", &["a", "b", "c"]), ("B", &["d", "e"])])
+    }
+
+    #[test]
+    fn after_pivot() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "b", false, &["new"], &[])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "new", "c"]);
+        assert_eq!(backend.workspace_names("B"), ["d", "e"]);
+        assert_eq!(backend.focused(), Some("new"));
+        Ok(())
+    }
+
+    #[test]
+    fn before_pivot() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "b", true, &["new"], &[])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "new", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn before_first() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "a", true, &["new"], &[])?;
+        assert_eq!(backend.workspace_names("A"), ["new", "a", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn after_last() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "c", false, &["new"], &[])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "c", "new"]);
+        Ok(())
+    }
+
+    #[test]
+    fn on_other_output() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "d", false, &["new"], &[])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "c"]);
+        assert_eq!(backend.workspace_names("B"), ["d", "new", "e"]);
+        Ok(())
+    }
+
+    #[test]
+    fn multiple_names() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "a", false, &["x", "y", "z"], &[])?;
+        assert_eq!(backend.focused(), Some("z"));
+        // Empty workspaces vanish once they lose focus, only the last new one remains
+        assert_eq!(backend.workspace_names("A"), ["a", "z", "b", "c"]);
+        Ok(())
+    }
+
+    #[test]
+    fn moved_container_keeps_focus() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "a", false, &["new"], &[1])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "new", "b", "c"]);
+        assert_eq!(backend.containers("new"), Some([1].as_slice()));
+        assert_eq!(backend.focused(), Some("a"));
+        Ok(())
+    }
+
+    #[test]
+    fn moved_container_from_other_output() -> Result<(), InsertionError> {
+        let mut backend = backend();
+        insert(&mut backend, "d", true, &["new"], &[1])?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "c"]);
+        assert_eq!(backend.workspace_names("B"), ["new", "d", "e"]);
+        assert_eq!(backend.containers("new"), Some([1].as_slice()));
+        assert_eq!(backend.focused(), Some("a"));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_pivot() {
+        let mut backend = backend();
+        assert!(matches!(
+            insert(&mut backend, "f", false, &["new"], &[]),
+            Err(InsertionError::NoPivotWorkspace(name)) if name == "f"
+        ));
+        assert!(backend.commands().is_empty());
+    }
 }
@@ -0,0 +1,322 @@
+//! Insert workspaces with the method suited for the window manager
+use serde::Deserialize;
+use thiserror::Error;
+
+#[cfg(feature = "async")]
+use crate::async_ipc::AsyncIpc;
+#[cfg(feature = "swayipc")]
+use crate::sway::SwayConnection;
+use crate::{
+    backend::WmBackend,
+    connection::Connection,
+    insert_workspace_rename::{
+        self, insert_workspace as insert_workspace_rename, InsertionError as RenameInsertionError,
+    },
+    insert_workspace_swap::{
+        self, insert_workspace as insert_workspace_swap, InsertionError as SwapInsertionError,
+    },
+    plan::Plan,
+    raw_tree::{self, RawTreeError},
+    retry::RetryPolicy,
+    util::{renamed, ContainerChanges, InsertionDestination, WorkspaceRename},
+};
+
+/// Method to insert workspaces
+#[derive(Clone, Debug)]
+pub enum InsertMode {
+    I3,
+    Sway,
+}
+
+impl InsertMode {
+    /// Method for the window manager with the version reply `variant`, `sway` for sway and none for i3
+    pub(crate) fn from_variant(variant: Option<&str>) -> Self {
+        if variant == Some("sway") {
+            Self::Sway
+        } else {
+            Self::I3
+        }
+    }
+}
+
+/// Method to insert workspaces as selected by `--mode`
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum ModeSelection {
+    /// Detect whether i3 or sway is running
+    Auto,
+    I3,
+    Sway,
+}
+
+impl ModeSelection {
+    /// Resolve `Auto` to the method of the running window manager
+    ///
+    /// Decided by `SWAYSOCK` or `I3SOCK` if set, otherwise by the version reply of the window manager.
+    /// The environment says nothing about an `explicit_socket` given by `--socket`, so it is always asked.
+    /// Defaults to i3 if the window manager can not be reached, retrying following `retry`
+    #[must_use]
+    pub fn resolve(self, explicit_socket: bool, retry: RetryPolicy) -> InsertMode {
+        match self {
+            Self::I3 => InsertMode::I3,
+            Self::Sway => InsertMode::Sway,
+            Self::Auto if explicit_socket => Self::detect(retry),
+            Self::Auto if std::env::var_os("SWAYSOCK").is_some() => InsertMode::Sway,
+            Self::Auto if std::env::var_os("I3SOCK").is_some() => InsertMode::I3,
+            Self::Auto => Self::detect(retry),
+        }
+    }
+
+    /// Ask the window manager whether it is sway
+    fn detect(retry: RetryPolicy) -> InsertMode {
+        match raw_tree::get_variant(retry) {
+            Ok(variant) => InsertMode::from_variant(variant.as_deref()),
+            Err(error) => {
+                log::debug!("Could not detect the window manager, assuming i3: {error}");
+                InsertMode::I3
+            }
+        }
+    }
+}
+
+/// Layout of the new workspace
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum Layout {
+    Tabbed,
+    Stacking,
+    Splith,
+    Splitv,
+}
+
+impl Layout {
+    /// Commands setting the layout of the new workspace `name`, after `containers` were moved to it
+    ///
+    /// Without moved containers the new workspace is focused to set its layout
+    pub fn commands(self, name: &str, containers: &[i64]) -> Vec<String> {
+        let layout = match self {
+            Self::Tabbed => "tabbed",
+            Self::Stacking => "stacking",
+            Self::Splith => "splith",
+            Self::Splitv => "splitv",
+        };
+        // Changing the layout of a container changes the layout of its parent, the workspace
+        containers.first().map_or_else(
+            || vec![format!("workspace \"{name}\""), format!("layout {layout}")],
+            |container| vec![format!("[con_id={container}] layout {layout}")],
+        )
+    }
+}
+
+/// Placement of floating containers moved to the new workspace
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FloatPlacement {
+    /// Keep the position the window manager chooses
+    Keep,
+    /// Center them on the output
+    Center,
+    /// Place them under the mouse pointer
+    Pointer,
+}
+
+impl FloatPlacement {
+    /// Commands placing the floating ones of `containers`
+    pub fn commands(
+        self,
+        conn: &mut Connection,
+        containers: &[i64],
+    ) -> Result<Vec<String>, RawTreeError> {
+        let position = match self {
+            Self::Keep => return Ok(Vec::new()),
+            Self::Center => "center",
+            Self::Pointer => "mouse",
+        };
+        let tree = raw_tree::get_tree(conn)?;
+        Ok(containers
+            .iter()
+            .filter(|x| tree.is_floating(**x))
+            .map(|x| format!("[con_id={x}] move position {position}"))
+            .collect())
+    }
+}
+
+/// Options choosing the focus after the insertion
+///
+/// Without them, the focus depends on the insertion method and on whether containers are moved
+#[derive(clap::Args, Default, Debug)]
+pub struct FollowSelection {
+    /// Keep the originating workspace focused after the insertion
+    #[clap(long, env = "I3IW_NO_FOLLOW")]
+    no_follow: bool,
+
+    /// Focus the new workspace after the insertion, and the first moved container inside it
+    #[clap(long, env = "I3IW_FOLLOW", conflicts_with = "no_follow")]
+    follow: bool,
+}
+
+impl FollowSelection {
+    /// Use `follow` of the config file, if neither `--follow` nor `--no-follow` is given
+    ///
+    /// `true` acts like `--follow`, `false` like `--no-follow`
+    pub fn default_to(&mut self, follow: Option<bool>) {
+        if !self.follow && !self.no_follow {
+            self.follow = follow == Some(true);
+            self.no_follow = follow == Some(false);
+        }
+    }
+
+    /// Commands run after the insertion to establish the requested focus
+    ///
+    /// `origin` is the workspace focused before the insertion, `renames` were applied during it.
+    /// `containers` were moved to the new workspace `name`
+    pub fn commands(
+        &self,
+        origin: &str,
+        renames: &[WorkspaceRename],
+        name: &str,
+        containers: &[i64],
+    ) -> Vec<String> {
+        if self.no_follow {
+            vec![format!("workspace \"{}\"", renamed(renames, origin))]
+        } else if self.follow {
+            containers.first().map_or_else(
+                || vec![format!("workspace \"{name}\"")],
+                |container| vec![format!("[con_id={container}] focus")],
+            )
+        } else {
+            Vec::new()
+        }
+    }
+}
+
+/// Errors of the insertion methods
+#[derive(Debug, Error)]
+pub enum InsertionError {
+    #[error("Error during sway insertion: {0}")]
+    SwapInsertion(
+        #[from]
+        #[source]
+        SwapInsertionError,
+    ),
+    #[error("Error during i3 insertion: {0}")]
+    RenameInsertion(
+        #[from]
+        #[source]
+        RenameInsertionError,
+    ),
+    #[cfg(feature = "async")]
+    #[error("Could not connect asynchronously: {0}")]
+    AsyncConnect(#[source] std::io::Error),
+}
+
+/// Insert workspaces named `names` at `destination` through `backend`, using the method for `mode`
+///
+/// `containers` are changed by `changes`, `renames` are applied before the insertion.
+/// Returns the plan of the insertion
+pub fn insert_with(
+    backend: &mut impl WmBackend,
+    mode: &InsertMode,
+    destination: &InsertionDestination,
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    Ok(match mode {
+        InsertMode::I3 => {
+            insert_workspace_rename(backend, destination, names, containers, changes, renames)?
+        }
+        InsertMode::Sway => {
+            insert_workspace_swap(backend, destination, names, containers, changes, renames)?
+        }
+    })
+}
+
+/// Plan the insertion of workspaces named `names` at `destination` with the method for `mode`, without running it
+///
+/// `containers` are changed by `changes`, `renames` are applied before the insertion
+pub fn plan_with(
+    backend: &mut impl WmBackend,
+    mode: &InsertMode,
+    destination: &InsertionDestination,
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    Ok(match mode {
+        InsertMode::I3 => insert_workspace_rename::insertion_plan(
+            &backend
+                .get_workspaces()
+                .map_err(RenameInsertionError::from)?,
+            destination,
+            names,
+            containers,
+            changes,
+            renames,
+        )?,
+        InsertMode::Sway => insert_workspace_swap::insertion_plan(
+            &backend.get_tree().map_err(SwapInsertionError::from)?,
+            destination,
+            names,
+            containers,
+            changes,
+            renames,
+        )?,
+    })
+}
+
+/// Insert workspaces named `names` at `destination` using the method for `mode`
+///
+/// `containers` are changed by `changes`.
+/// Commands sent on other connections are recorded on `conn`, in dry-run mode only `conn` is used to plan them.
+/// Runs through `tokio-i3ipc` if `conn` was told to use it.
+/// Returns the plan of the insertion
+pub fn run_insertion(
+    conn: &mut Connection,
+    mode: &InsertMode,
+    destination: &InsertionDestination,
+    names: &[String],
+    containers: &[i64],
+    changes: ContainerChanges,
+    renames: &[WorkspaceRename],
+) -> Result<Plan, InsertionError> {
+    #[cfg(feature = "async")]
+    if conn.uses_async_ipc() && !conn.is_dry_run() {
+        let mut async_ipc =
+            AsyncIpc::connect(conn.retry_policy()).map_err(InsertionError::AsyncConnect)?;
+        let plan = insert_with(
+            &mut async_ipc,
+            mode,
+            destination,
+            names,
+            containers,
+            changes,
+            renames,
+        )?;
+        conn.record(&plan.commands.join("; "));
+        return Ok(plan);
+    }
+    // Prefer swayipc on the same socket, but the i3 compatible IPC works as well
+    #[cfg(feature = "swayipc")]
+    if matches!(mode, InsertMode::Sway) && !conn.is_dry_run() {
+        match SwayConnection::connect() {
+            Ok(mut sway) => {
+                let plan = insert_with(
+                    &mut sway,
+                    mode,
+                    destination,
+                    names,
+                    containers,
+                    changes,
+                    renames,
+                )?;
+                conn.record(&plan.commands.join("; "));
+                return Ok(plan);
+            }
+            Err(error) => {
+                log::debug!("Falling back to i3ipc, could not connect to sway: {error}");
+            }
+        }
+    }
+    insert_with(conn, mode, destination, names, containers, changes, renames)
+}
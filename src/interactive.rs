@@ -0,0 +1,371 @@
+//! Choose the insertion point and name in a terminal list, instead of on the command line
+use std::io::Stderr;
+
+use i3ipc::reply::Workspace;
+use ratatui::{
+    backend::CrosstermBackend,
+    crossterm::{
+        event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
+        execute,
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+    },
+    layout::{Constraint, Layout},
+    style::{Modifier, Style},
+    widgets::{Block, List, ListState, Paragraph},
+    Frame, Terminal,
+};
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    naming::NameSelection,
+    util::{get_workspaces, Pivot},
+};
+
+/// Errors while asking for the insertion
+#[derive(Debug, Error)]
+pub enum InteractiveError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not use the terminal: {0}")]
+    Terminal(#[from] std::io::Error),
+    #[error("Cancelled")]
+    Cancelled,
+}
+
+/// Options of the `interactive` subcommand
+#[derive(clap::Args, Debug)]
+pub struct InteractiveArgs {
+    /// Naming of the new workspace, if no name is typed
+    #[clap(flatten)]
+    pub name: NameSelection,
+}
+
+impl InteractiveArgs {
+    /// List the workspaces of each output with the insertion points between them, and ask for one of them and a name
+    ///
+    /// Returns the pivot, whether to insert before it, and the naming with the typed name.
+    /// The list is drawn on stderr, so it works while stdout is captured
+    pub fn run(
+        mut self,
+        conn: &mut Connection,
+    ) -> Result<(Pivot, bool, NameSelection), InteractiveError> {
+        let workspaces = get_workspaces(conn)?;
+        let points = insertion_points(&workspaces);
+        let mut picker = Picker::new(&points);
+
+        let mut terminal = RawTerminal::new()?;
+        let (point, name) = loop {
+            terminal.0.draw(|frame| picker.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match picker.handle(key) {
+                Step::Continue => {}
+                Step::Cancel => return Err(InteractiveError::Cancelled),
+                Step::Done(point, name) => break (point, name),
+            }
+        };
+        drop(terminal);
+
+        let Some((workspace, before)) = points.get(point) else {
+            return Err(InteractiveError::Cancelled);
+        };
+        if !name.is_empty() {
+            self.name.set_name(name);
+        }
+        let pivot = Pivot {
+            name: workspace.name.clone(),
+            output: Some(workspace.output.clone()),
+        };
+        Ok((pivot, *before, self.name))
+    }
+}
+
+/// Positions a workspace can be inserted at, as pivot and whether to insert before it
+///
+/// Each output has a position before each of its workspaces, and one after its last workspace
+fn insertion_points(workspaces: &[Workspace]) -> Vec<(&Workspace, bool)> {
+    let mut points = Vec::new();
+    for (index, workspace) in workspaces.iter().enumerate() {
+        points.push((workspace, true));
+        let last_on_output = workspaces
+            .get(index + 1)
+            .is_none_or(|next| next.output != workspace.output);
+        if last_on_output {
+            points.push((workspace, false));
+        }
+    }
+    points
+}
+
+/// Result of a key press in the picker
+#[derive(Debug, PartialEq, Eq)]
+enum Step {
+    Continue,
+    Cancel,
+    /// Insert at the insertion point with this index, with the typed name
+    Done(usize, String),
+}
+
+/// State of the list of outputs, workspaces and insertion points, and of the name being typed
+struct Picker {
+    /// Text of each row, and the index of the insertion point for rows which are one
+    rows: Vec<(String, Option<usize>)>,
+    /// Row of the selected insertion point
+    selected: usize,
+    /// Name being typed, once an insertion point is chosen
+    name: Option<String>,
+}
+
+impl Picker {
+    /// Build the rows for `points`, selecting the position after the focused workspace
+    fn new(points: &[(&Workspace, bool)]) -> Self {
+        let mut rows = Vec::new();
+        let mut output = None;
+        for (index, (workspace, before)) in points.iter().enumerate() {
+            if output != Some(&workspace.output) {
+                rows.push((workspace.output.clone(), None));
+                output = Some(&workspace.output);
+            }
+            rows.push(("  ┄┄┄┄".to_owned(), Some(index)));
+            // The workspace follows the position before it
+            if *before {
+                let focus = if workspace.focused { "*" } else { " " };
+                rows.push((format!("  {focus} {}", workspace.name), None));
+            }
+        }
+        // The position right after the focused workspace
+        let selected = points
+            .iter()
+            .position(|(workspace, before)| workspace.focused && *before)
+            .and_then(|point| rows.iter().position(|x| x.1 == Some(point + 1)))
+            .or_else(|| rows.iter().position(|x| x.1.is_some()))
+            .unwrap_or_default();
+        Self {
+            rows,
+            selected,
+            name: None,
+        }
+    }
+
+    /// Update the state for `key`
+    fn handle(&mut self, key: KeyEvent) -> Step {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Step::Cancel;
+        }
+        let Some(name) = &mut self.name else {
+            match key.code {
+                KeyCode::Up | KeyCode::Char('k') => {
+                    if let Some(row) = self
+                        .rows
+                        .iter()
+                        .take(self.selected)
+                        .rposition(|x| x.1.is_some())
+                    {
+                        self.selected = row;
+                    }
+                }
+                KeyCode::Down | KeyCode::Char('j') => {
+                    if let Some(offset) = self
+                        .rows
+                        .iter()
+                        .skip(self.selected + 1)
+                        .position(|x| x.1.is_some())
+                    {
+                        self.selected += offset + 1;
+                    }
+                }
+                KeyCode::Enter => self.name = Some(String::new()),
+                KeyCode::Esc | KeyCode::Char('q') => return Step::Cancel,
+                _ => {}
+            }
+            return Step::Continue;
+        };
+        match key.code {
+            KeyCode::Char(character) => name.push(character),
+            KeyCode::Backspace => {
+                name.pop();
+            }
+            KeyCode::Enter => {
+                let point = self
+                    .rows
+                    .get(self.selected)
+                    .and_then(|x| x.1)
+                    .unwrap_or_default();
+                return Step::Done(point, std::mem::take(name));
+            }
+            // Back to choosing the insertion point
+            KeyCode::Esc => self.name = None,
+            _ => {}
+        }
+        Step::Continue
+    }
+
+    /// Draw the list with the selected insertion point, and the name below it
+    fn draw(&self, frame: &mut Frame) {
+        let [list_area, name_area] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(3)]).areas(frame.area());
+        let list = List::new(self.rows.iter().map(|x| x.0.as_str()))
+            .block(Block::bordered().title("Insert at (↑/↓, Enter, Esc)"))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        let mut state = ListState::default().with_selected(Some(self.selected));
+        frame.render_stateful_widget(list, list_area, &mut state);
+        let name = self
+            .name
+            .as_ref()
+            .map_or_else(String::new, |x| format!("{x}▏"));
+        frame.render_widget(
+            Paragraph::new(name).block(Block::bordered().title("Name (empty to generate one)")),
+            name_area,
+        );
+    }
+}
+
+/// Terminal on stderr in raw mode and on the alternate screen, restored when dropped
+struct RawTerminal(Terminal<CrosstermBackend<Stderr>>);
+
+impl RawTerminal {
+    fn new() -> std::io::Result<Self> {
+        enable_raw_mode()?;
+        let mut stderr = std::io::stderr();
+        let terminal = execute!(stderr, EnterAlternateScreen)
+            .and_then(|()| Terminal::new(CrosstermBackend::new(stderr)));
+        match terminal {
+            Ok(terminal) => Ok(Self(terminal)),
+            Err(error) => {
+                // Dropping only restores a terminal which was set up completely
+                if let Err(error) = disable_raw_mode() {
+                    log::debug!("Could not leave raw mode: {error}");
+                }
+                Err(error)
+            }
+        }
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let restored = disable_raw_mode()
+            .and_then(|()| execute!(self.0.backend_mut(), LeaveAlternateScreen))
+            .and_then(|()| self.0.show_cursor());
+        if let Err(error) = restored {
+            log::warn!("Could not restore the terminal: {error}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use i3ipc::reply::Workspace;
+    use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+    use super::{insertion_points, Picker, Step};
+
+    fn workspace(name: &str, output: &str, focused: bool) -> Workspace {
+        Workspace {
+            num: -1,
+            name: name.to_owned(),
+            visible: focused,
+            focused,
+            urgent: false,
+            rect: (0, 0, 0, 0),
+            output: output.to_owned(),
+        }
+    }
+
+    fn workspaces() -> Vec<Workspace> {
+        vec![
+            workspace("a", "A", false),
+            workspace("b", "A", true),
+            workspace("c", "B", false),
+        ]
+    }
+
+    fn press(picker: &mut Picker, keys: &[KeyCode]) -> Vec<Step> {
+        keys.iter()
+            .map(|x| picker.handle(KeyEvent::from(*x)))
+            .collect()
+    }
+
+    #[test]
+    fn lists_outputs_and_points() {
+        let workspaces = workspaces();
+        let points = insertion_points(&workspaces);
+        let picker = Picker::new(&points);
+        let rows: Vec<_> = picker.rows.iter().map(|x| (x.0.trim(), x.1)).collect();
+        assert_eq!(
+            rows,
+            [
+                ("A", None),
+                ("┄┄┄┄", Some(0)),
+                ("a", None),
+                ("┄┄┄┄", Some(1)),
+                ("* b", None),
+                ("┄┄┄┄", Some(2)),
+                ("B", None),
+                ("┄┄┄┄", Some(3)),
+                ("c", None),
+                ("┄┄┄┄", Some(4)),
+            ]
+        );
+        // After the focused workspace
+        assert_eq!(picker.selected, 5);
+    }
+
+    #[test]
+    fn chooses_point_and_name() {
+        let workspaces = workspaces();
+        let points = insertion_points(&workspaces);
+        let mut picker = Picker::new(&points);
+        press(&mut picker, &[KeyCode::Down, KeyCode::Char('j')]);
+        assert_eq!(picker.rows.get(picker.selected).and_then(|x| x.1), Some(4));
+        // Stays on the last point
+        press(&mut picker, &[KeyCode::Down]);
+        assert_eq!(picker.rows.get(picker.selected).and_then(|x| x.1), Some(4));
+        press(&mut picker, &[KeyCode::Up, KeyCode::Up, KeyCode::Char('k')]);
+        assert_eq!(picker.rows.get(picker.selected).and_then(|x| x.1), Some(1));
+
+        let steps = press(
+            &mut picker,
+            &[
+                KeyCode::Enter,
+                KeyCode::Char('m'),
+                KeyCode::Char('k'),
+                KeyCode::Char('x'),
+                KeyCode::Backspace,
+                KeyCode::Enter,
+            ],
+        );
+        assert_eq!(steps.last(), Some(&Step::Done(1, "mk".to_owned())));
+    }
+
+    #[test]
+    fn cancels() {
+        let workspaces = workspaces();
+        let points = insertion_points(&workspaces);
+        let mut picker = Picker::new(&points);
+        // Escape while typing goes back to the list
+        let steps = press(
+            &mut picker,
+            &[
+                KeyCode::Enter,
+                KeyCode::Char('q'),
+                KeyCode::Esc,
+                KeyCode::Char('q'),
+            ],
+        );
+        assert_eq!(
+            steps,
+            [Step::Continue, Step::Continue, Step::Continue, Step::Cancel]
+        );
+        let mut picker = Picker::new(&points);
+        assert_eq!(
+            picker.handle(KeyEvent::new(KeyCode::Char('c'), KeyModifiers::CONTROL)),
+            Step::Cancel
+        );
+    }
+}
@@ -0,0 +1,143 @@
+#![forbid(unsafe_code)]
+// Classes
+#![warn(clippy::pedantic, clippy::cargo, rustdoc::all)]
+// Stability
+#![warn(clippy::expect_used, clippy::unwrap_used, clippy::indexing_slicing)]
+// Nice code
+#![warn(
+    clippy::unneeded_field_pattern,
+    clippy::unneeded_wildcard_pattern,
+    clippy::option_if_let_else
+)]
+// Debug remains
+#![warn(
+    clippy::use_debug,
+    clippy::print_stdout,
+    clippy::todo,
+    clippy::unimplemented,
+    clippy::dbg_macro
+)]
+
+//! Workspace enhancement for the i3 window manager
+//! Insert a named workspace before or after another named workspace
+//!
+//! The library holds the insertion used by the `i3-insert-workspace` binary, for embedding it in other i3 tools:
+//!
+//! ```no_run
+//! use i3_insert_workspace::{
+//!     insert_workspace, Connection, InsertionDestination, Pivot, RetryPolicy, WmBackend,
+//! };
+//!
+//! let mut conn = Connection::connect(RetryPolicy::default())?;
+//! let mode = conn.get_mode()?;
+//! let pivot = Pivot {
+//!     name: "1".to_owned(),
+//!     output: None,
+//! };
+//! insert_workspace(
+//!     &mut conn,
+//!     &mode,
+//!     &InsertionDestination::new(pivot, false),
+//!     &["2".to_owned()],
+//! )?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+#[cfg(feature = "async")]
+mod async_ipc;
+mod backend;
+mod bindings;
+mod bound_names;
+pub mod cli;
+mod completions;
+mod config;
+mod connection;
+mod container;
+mod criteria;
+mod docker_name;
+mod duplicate;
+mod insert_workspace_rename;
+mod insert_workspace_swap;
+mod insertion;
+mod interactive;
+mod logging;
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+mod naming;
+mod numbered;
+mod pivot;
+mod plan;
+mod process;
+mod query;
+mod raw_tree;
+mod remove;
+mod reorder;
+mod report;
+mod request;
+mod retry;
+mod rofi;
+#[cfg(feature = "swayipc")]
+mod sway;
+mod util;
+
+pub use backend::{BackendError, WmBackend};
+pub use connection::Connection;
+pub use insert_workspace_rename::InsertionError as RenameInsertionError;
+pub use insert_workspace_swap::InsertionError as SwapInsertionError;
+pub use insertion::{InsertMode, InsertionError, ModeSelection};
+#[cfg(feature = "mock")]
+pub use mock::MockBackend;
+pub use plan::Plan;
+pub use raw_tree::{RawNode, RawTreeError, Rect, WindowProperties};
+pub use request::{Focus, InsertRequest, InsertRequestBuilder, RequestError};
+pub use retry::RetryPolicy;
+#[cfg(feature = "swayipc")]
+pub use sway::SwayError;
+pub use util::{InsertionDestination, Pivot};
+
+/// Insert new workspaces named `names` at `destination`, in this order, using the insertion method for `mode`
+///
+/// Returns the plan which was run
+///
+/// # Errors
+///
+/// Fails if the pivot of `destination` does not exist, if the window manager can not be reached,
+/// or if it rejects one of the commands
+pub fn insert_workspace(
+    backend: &mut impl WmBackend,
+    mode: &InsertMode,
+    destination: &InsertionDestination,
+    names: &[String],
+) -> Result<Plan, InsertionError> {
+    insertion::insert_with(
+        backend,
+        mode,
+        destination,
+        names,
+        &[],
+        util::ContainerChanges::default(),
+        &[],
+    )
+}
+
+/// Plan the insertion of new workspaces named `names` at `destination` like `insert_workspace`, without running it
+///
+/// # Errors
+///
+/// Fails if the pivot of `destination` does not exist, or if the window manager can not be reached
+pub fn plan_insertion(
+    backend: &mut impl WmBackend,
+    mode: &InsertMode,
+    destination: &InsertionDestination,
+    names: &[String],
+) -> Result<Plan, InsertionError> {
+    insertion::plan_with(
+        backend,
+        mode,
+        destination,
+        names,
+        &[],
+        util::ContainerChanges::default(),
+        &[],
+    )
+}
@@ -0,0 +1,63 @@
+//! Diagnostics on stderr, with the verbosity set on the command line
+use clap::ArgAction;
+use log::{LevelFilter, Log, Metadata, Record};
+
+/// Logger writing each record as a line on stderr
+struct StderrLogger;
+
+impl Log for StderrLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            eprintln!(
+                "{}: {}",
+                record.level().as_str().to_lowercase(),
+                record.args()
+            );
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: StderrLogger = StderrLogger;
+
+/// Options controlling what is printed on stderr
+#[derive(clap::Args, Debug)]
+pub struct VerbosityArgs {
+    /// Print more details on stderr, repeat for more.
+    ///
+    /// `-v` logs the commands sent to the window manager, `-vv` also the IPC requests, the planned insertion
+    /// and the outcome of each command, `-vvv` also the received workspaces
+    #[clap(short, long, action = ArgAction::Count, global = true)]
+    verbose: u8,
+
+    /// Print nothing on stderr, not even errors.
+    ///
+    /// Failures are still reported by the exit code
+    #[clap(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
+}
+
+impl VerbosityArgs {
+    /// Install the logger with the requested verbosity
+    pub fn init(&self) {
+        let level = if self.quiet {
+            LevelFilter::Off
+        } else {
+            match self.verbose {
+                0 => LevelFilter::Warn,
+                1 => LevelFilter::Info,
+                2 => LevelFilter::Debug,
+                _ => LevelFilter::Trace,
+            }
+        };
+        // Only fails if a logger is already installed, which then keeps logging
+        if log::set_logger(&LOGGER).is_ok() {
+            log::set_max_level(level);
+        }
+    }
+}
@@ -0,0 +1,507 @@
+//! Window manager simulated in memory, to run the insertion methods without a running window manager
+//!
+//! The model is a list of workspaces with the ids of their top level containers.
+//! It follows i3 in the points the insertion methods rely on:
+//! new and renamed workspaces are appended to their output, and empty workspaces vanish once they are hidden
+use i3ipc::reply::{CommandOutcome, Workspace};
+
+use crate::{
+    backend::{BackendError, WmBackend},
+    insertion::InsertMode,
+    numbered::NumberedName,
+    raw_tree::RawNode,
+};
+
+/// Workspace of the simulated window manager
+#[derive(Clone, Debug)]
+struct MockWorkspace {
+    name: String,
+    output: String,
+    /// Ids of the top level containers, from the most to the least recently focused
+    containers: Vec<i64>,
+    /// Whether the workspace is shown on its output
+    visible: bool,
+}
+
+/// Window manager simulated in memory, applying the commands sent to it
+///
+/// `workspace`, `rename workspace`, `move workspace to output` and `move container to workspace` change the model,
+/// `focus` on a container focuses its workspace, `focus output` the workspace shown on the output.
+/// Commands only changing containers, like `mark` or `layout`, succeed without effect, others fail
+#[derive(Clone, Debug, Default)]
+pub struct MockBackend {
+    /// Workspaces in the order of their outputs
+    workspaces: Vec<MockWorkspace>,
+    /// Name of the focused workspace
+    focused: Option<String>,
+    commands: Vec<String>,
+}
+
+impl MockBackend {
+    /// Simulate a window manager with `workspaces`, in the order of their outputs
+    ///
+    /// The workspaces marked as focused and visible are focused and visible, the workspaces hold no containers
+    #[must_use]
+    pub fn new(workspaces: Vec<Workspace>) -> Self {
+        Self {
+            focused: workspaces
+                .iter()
+                .find(|x| x.focused)
+                .map(|x| x.name.clone()),
+            workspaces: workspaces
+                .into_iter()
+                .map(|x| MockWorkspace {
+                    name: x.name,
+                    output: x.output,
+                    containers: Vec::new(),
+                    visible: x.visible || x.focused,
+                })
+                .collect(),
+            commands: Vec::new(),
+        }
+    }
+
+    /// Put a top level container with the id `id` on the workspace `workspace`, returning whether it exists
+    pub fn add_container(&mut self, workspace: &str, id: i64) -> bool {
+        self.workspace_mut(workspace)
+            .map(|x| x.containers.insert(0, id))
+            .is_some()
+    }
+
+    /// Commands run so far, each message split into its commands
+    #[must_use]
+    pub fn commands(&self) -> &[String] {
+        &self.commands
+    }
+
+    /// Names of the workspaces of `output`, in order
+    #[must_use]
+    pub fn workspace_names(&self, output: &str) -> Vec<&str> {
+        self.workspaces
+            .iter()
+            .filter(|x| x.output == output)
+            .map(|x| x.name.as_str())
+            .collect()
+    }
+
+    /// Ids of the top level containers on the workspace `workspace`, if it exists
+    #[must_use]
+    pub fn containers(&self, workspace: &str) -> Option<&[i64]> {
+        self.workspaces
+            .iter()
+            .find(|x| x.name == workspace)
+            .map(|x| x.containers.as_slice())
+    }
+
+    /// Name of the focused workspace
+    #[must_use]
+    pub fn focused(&self) -> Option<&str> {
+        self.focused.as_deref()
+    }
+
+    /// Simulate `outputs` holding the named workspaces, each with a single container with ids counted from 1
+    ///
+    /// The first workspace of each output is shown, the first one of all is focused
+    #[cfg(test)]
+    pub(crate) fn with_outputs(outputs: &[(&str, &[&str])]) -> Self {
+        let mut workspaces = outputs
+            .iter()
+            .flat_map(|(output, names)| {
+                names.iter().enumerate().map(|(index, name)| MockWorkspace {
+                    name: (*name).to_owned(),
+                    output: (*output).to_owned(),
+                    containers: Vec::new(),
+                    visible: index == 0,
+                })
+            })
+            .collect::<Vec<_>>();
+        for (id, workspace) in (1..).zip(&mut workspaces) {
+            workspace.containers.push(id);
+        }
+        Self {
+            focused: workspaces.first().map(|x| x.name.clone()),
+            workspaces,
+            commands: Vec::new(),
+        }
+    }
+
+    fn workspace_mut(&mut self, name: &str) -> Option<&mut MockWorkspace> {
+        self.workspaces.iter_mut().find(|x| x.name == name)
+    }
+
+    fn focused_output(&self) -> String {
+        self.focused
+            .as_ref()
+            .and_then(|focused| self.workspaces.iter().find(|x| &x.name == focused))
+            .map(|x| x.output.clone())
+            .unwrap_or_default()
+    }
+
+    /// Create the workspace `name` at the end of the focused output, unless it exists
+    fn ensure_workspace(&mut self, name: &str) {
+        if self.workspace_mut(name).is_none() {
+            let output = self.focused_output();
+            self.push_to_output(MockWorkspace {
+                name: name.to_owned(),
+                output,
+                containers: Vec::new(),
+                visible: false,
+            });
+        }
+    }
+
+    /// Insert `workspace` after the last workspace of its output
+    fn push_to_output(&mut self, workspace: MockWorkspace) {
+        let index = self
+            .workspaces
+            .iter()
+            .rposition(|x| x.output == workspace.output)
+            .map_or(self.workspaces.len(), |x| x + 1);
+        self.workspaces.insert(index, workspace);
+    }
+
+    /// Remove the workspace `name` and append it to the end of `output`
+    fn move_to_end(&mut self, name: &str, output: Option<&str>) {
+        if let Some(index) = self.workspaces.iter().position(|x| x.name == name) {
+            let mut workspace = self.workspaces.remove(index);
+            if let Some(output) = output {
+                output.clone_into(&mut workspace.output);
+            }
+            self.push_to_output(workspace);
+        }
+    }
+
+    /// Focus and show the workspace `name`, dropping the previously shown one of its output if it is empty
+    fn focus(&mut self, name: &str) {
+        self.focused = Some(name.to_owned());
+        let output = self.focused_output();
+        for workspace in self.workspaces.iter_mut().filter(|x| x.output == output) {
+            workspace.visible = workspace.name == name;
+        }
+        self.drop_empty();
+    }
+
+    /// Drop the empty workspaces which are not shown
+    fn drop_empty(&mut self) {
+        self.workspaces
+            .retain(|x| !x.containers.is_empty() || x.visible);
+    }
+
+    /// Apply a single command to the model
+    fn apply(&mut self, command: &str) -> Result<(), String> {
+        let (container, command) = split_criteria(command)?;
+        if let Some(name) = command.strip_prefix("workspace ") {
+            let name = unquote(name);
+            self.ensure_workspace(&name);
+            self.focus(&name);
+        } else if let Some(rename) = command.strip_prefix("rename workspace ") {
+            let (from, to) = split_rename(rename)?;
+            if from != to && self.workspace_mut(&to).is_some() {
+                return Err(format!("New workspace \"{to}\" already exists"));
+            }
+            let workspace = self
+                .workspace_mut(&from)
+                .ok_or_else(|| format!("Old workspace \"{from}\" not found"))?;
+            workspace.name.clone_from(&to);
+            if self.focused.as_ref() == Some(&from) {
+                self.focused = Some(to.clone());
+            }
+            self.move_to_end(&to, None);
+        } else if let Some(output) = command.strip_prefix("move workspace to output ") {
+            let focused = self.focused.clone().ok_or("No workspace is focused")?;
+            let output = unquote(output);
+            // Moving a workspace to its own output keeps its position
+            if self.focused_output() == output {
+                return Ok(());
+            }
+            // The moved workspace is shown on its new output, instead of the one shown there
+            for workspace in self.workspaces.iter_mut().filter(|x| x.output == output) {
+                workspace.visible = workspace.name == focused;
+            }
+            self.move_to_end(&focused, Some(&output));
+            self.drop_empty();
+        } else if let Some(name) = command.strip_prefix("move container to workspace ") {
+            let container = container.ok_or("No container given")?;
+            let name = unquote(name);
+            let source = self
+                .workspaces
+                .iter_mut()
+                .find(|x| x.containers.contains(&container))
+                .ok_or_else(|| format!("Container {container} not found"))?;
+            source.containers.retain(|x| *x != container);
+            self.ensure_workspace(&name);
+            if let Some(target) = self.workspace_mut(&name) {
+                target.containers.insert(0, container);
+            }
+            self.drop_empty();
+        } else if let Some(output) = command.strip_prefix("focus output ") {
+            let output = unquote(output);
+            let name = self
+                .workspaces
+                .iter()
+                .find(|x| x.output == output && x.visible)
+                .map(|x| x.name.clone())
+                .ok_or_else(|| format!("Output \"{output}\" not found"))?;
+            self.focus(&name);
+        } else if command == "focus" {
+            let container = container.ok_or("No container given")?;
+            let workspace = self
+                .workspaces
+                .iter_mut()
+                .find(|x| x.containers.contains(&container))
+                .ok_or_else(|| format!("Container {container} not found"))?;
+            workspace.containers.retain(|x| *x != container);
+            workspace.containers.insert(0, container);
+            let name = workspace.name.clone();
+            self.focus(&name);
+        } else if ![
+            "mark ",
+            "layout ",
+            "resize ",
+            "move position ",
+            "move absolute ",
+            "fullscreen ",
+            "urgent ",
+        ]
+        .iter()
+        .any(|x| command.starts_with(x))
+        {
+            return Err(format!("Unknown command \"{command}\""));
+        }
+        Ok(())
+    }
+}
+
+impl WmBackend for MockBackend {
+    fn get_workspaces(&mut self) -> Result<Vec<Workspace>, BackendError> {
+        Ok(self
+            .workspaces
+            .iter()
+            .map(|x| Workspace {
+                num: NumberedName::parse(&x.name).map_or(-1, |x| x.number),
+                name: x.name.clone(),
+                visible: x.visible,
+                focused: self.focused.as_ref() == Some(&x.name),
+                urgent: false,
+                rect: (0, 0, 0, 0),
+                output: x.output.clone(),
+            })
+            .collect())
+    }
+
+    /// Tree of outputs holding workspaces with the shown one focused first, which hold childless containers
+    fn get_tree(&mut self) -> Result<RawNode, BackendError> {
+        let mut outputs: Vec<RawNode> = Vec::new();
+        for (id, workspace) in (1..).zip(&self.workspaces) {
+            let focused = self.focused.as_ref() == Some(&workspace.name);
+            let node = RawNode {
+                id: -id,
+                name: Some(workspace.name.clone()),
+                nodetype: "workspace".to_owned(),
+                focused: focused && workspace.containers.is_empty(),
+                focus: workspace.containers.clone(),
+                nodes: (0..)
+                    .zip(&workspace.containers)
+                    .map(|(index, id)| RawNode {
+                        id: *id,
+                        nodetype: "con".to_owned(),
+                        focused: focused && index == 0,
+                        ..RawNode::default()
+                    })
+                    .collect(),
+                ..RawNode::default()
+            };
+            if let Some(output) = outputs
+                .iter_mut()
+                .find(|x| x.name.as_ref() == Some(&workspace.output))
+            {
+                if workspace.visible {
+                    output.focus.insert(0, node.id);
+                } else {
+                    output.focus.push(node.id);
+                }
+                output.nodes.push(node);
+            } else {
+                outputs.push(RawNode {
+                    id: -1000 - id,
+                    name: Some(workspace.output.clone()),
+                    nodetype: "output".to_owned(),
+                    focus: vec![node.id],
+                    nodes: vec![node],
+                    ..RawNode::default()
+                });
+            }
+        }
+        Ok(RawNode {
+            nodetype: "root".to_owned(),
+            nodes: outputs,
+            ..RawNode::default()
+        })
+    }
+
+    /// Both insertion methods work on the simulation, it answers like i3
+    fn get_mode(&mut self) -> Result<InsertMode, BackendError> {
+        Ok(InsertMode::I3)
+    }
+
+    fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, BackendError> {
+        let commands = split_commands(command);
+        let outcomes = commands
+            .iter()
+            .map(|command| match self.apply(command) {
+                Ok(()) => CommandOutcome {
+                    success: true,
+                    error: None,
+                },
+                Err(error) => CommandOutcome {
+                    success: false,
+                    error: Some(error),
+                },
+            })
+            .collect();
+        self.commands.extend(commands);
+        Ok(outcomes)
+    }
+}
+
+/// Split a message at the semicolons outside of quotes, into its trimmed commands
+fn split_commands(message: &str) -> Vec<String> {
+    let mut commands = vec![String::new()];
+    let mut quoted = false;
+    let mut escaped = false;
+    for character in message.chars() {
+        if character == ';' && !quoted {
+            commands.push(String::new());
+            continue;
+        }
+        if character == '"' && !escaped {
+            quoted = !quoted;
+        }
+        escaped = character == '\\' && !escaped;
+        if let Some(command) = commands.last_mut() {
+            command.push(character);
+        }
+    }
+    commands
+        .into_iter()
+        .map(|x| x.trim().to_owned())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Split off a leading `[con_id=…]` criterion, returning the container id and the rest of the command
+fn split_criteria(command: &str) -> Result<(Option<i64>, &str), String> {
+    let Some(criteria) = command.strip_prefix('[') else {
+        return Ok((None, command));
+    };
+    let (criteria, rest) = criteria
+        .split_once(']')
+        .ok_or_else(|| format!("Unterminated criteria in \"{command}\""))?;
+    let id = criteria
+        .strip_prefix("con_id=")
+        .and_then(|x| x.parse().ok())
+        .ok_or_else(|| format!("Unsupported criteria \"{criteria}\""))?;
+    Ok((Some(id), rest.trim()))
+}
+
+/// Split the arguments of `rename workspace` into the old and the new name
+fn split_rename(arguments: &str) -> Result<(String, String), String> {
+    let arguments = arguments.trim();
+    let (from, to) = if arguments.starts_with('"') {
+        let end = quoted_end(arguments)
+            .ok_or_else(|| format!("Unterminated quote in \"{arguments}\""))?;
+        let (from, rest) = arguments.split_at(end);
+        (from, rest.trim_start().strip_prefix("to "))
+    } else {
+        arguments
+            .split_once(" to ")
+            .map_or((arguments, None), |(from, to)| (from, Some(to)))
+    };
+    let to = to.ok_or_else(|| format!("Missing new name in \"{arguments}\""))?;
+    Ok((unquote(from), unquote(to)))
+}
+
+/// Byte index after the closing quote of the string starting with a quote
+fn quoted_end(text: &str) -> Option<usize> {
+    let mut escaped = false;
+    text.char_indices().skip(1).find_map(|(index, character)| {
+        let end = character == '"' && !escaped;
+        escaped = character == '\\' && !escaped;
+        end.then_some(index + 1)
+    })
+}
+
+/// Remove the surrounding quotes of a name, and the escapes of quotes inside it
+fn unquote(name: &str) -> String {
+    let name = name.trim();
+    name.strip_prefix('"')
+        .and_then(|x| x.strip_suffix('"'))
+        .map_or_else(|| name.to_owned(), |x| x.replace("\\\"", "\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use i3ipc::reply::Workspace;
+
+    use super::{split_commands, MockBackend};
+    use crate::backend::{BackendError, WmBackend};
+
+    #[test]
+    fn numbers_like_i3() -> Result<(), BackendError> {
+        let mut backend = MockBackend::with_outputs(&[("A", &["3: web", "mail", "12"])]);
+        let numbers = backend
+            .get_workspaces()?
+            .into_iter()
+            .map(|x| x.num)
+            .collect::<Vec<_>>();
+        assert_eq!(numbers, [3, -1, 12]);
+        Ok(())
+    }
+
+    #[test]
+    fn splits_outside_of_quotes() {
+        assert_eq!(
+            split_commands("workspace \"a; b\"; rename workspace \"c\" to \"d\";"),
+            ["workspace \"a; b\"", "rename workspace \"c\" to \"d\""]
+        );
+    }
+
+    #[test]
+    fn renames_to_end_of_output() -> Result<(), BackendError> {
+        let mut backend = MockBackend::with_outputs(&[("A", &["a", "b", "c"]), ("B", &["d"])]);
+        backend.run_command("rename workspace \"a\" to \"e\"")?;
+        assert_eq!(backend.workspace_names("A"), ["b", "c", "e"]);
+        assert_eq!(backend.workspace_names("B"), ["d"]);
+        assert_eq!(backend.focused(), Some("e"));
+        Ok(())
+    }
+
+    #[test]
+    fn moves_containers() -> Result<(), BackendError> {
+        let mut backend = MockBackend::new(vec![Workspace {
+            num: 1,
+            name: "1".to_owned(),
+            visible: true,
+            focused: true,
+            urgent: false,
+            rect: (0, 0, 0, 0),
+            output: "A".to_owned(),
+        }]);
+        assert!(backend.add_container("1", 7));
+        assert!(!backend.add_container("2", 8));
+        backend.run_command("[con_id=7] move container to workspace 2")?;
+        assert_eq!(backend.containers("1"), Some([].as_slice()));
+        assert_eq!(backend.containers("2"), Some([7].as_slice()));
+        assert_eq!(backend.workspace_names("A"), ["1", "2"]);
+        Ok(())
+    }
+
+    #[test]
+    fn drops_hidden_empty_workspaces() -> Result<(), BackendError> {
+        let mut backend = MockBackend::with_outputs(&[("A", &["a", "b"])]);
+        backend.run_command("workspace new; workspace a")?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b"]);
+        assert!(!backend.run_command("kill")?.iter().all(|x| x.success));
+        Ok(())
+    }
+}
@@ -0,0 +1,838 @@
+//! Choose the name of the new workspace
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+};
+
+use i3ipc::reply::Workspace;
+use rand::{rngs::StdRng, SeedableRng};
+use regex::Regex;
+use serde::Deserialize;
+use thiserror::Error;
+use unicode_segmentation::UnicodeSegmentation;
+
+use crate::{
+    bound_names,
+    config::Config,
+    connection::Connection,
+    docker_name::{self, Theme},
+    numbered::Numbering,
+    process,
+    raw_tree::{self, RawTreeError},
+    util::{get_workspaces, Pivot},
+};
+
+/// Errors while naming the new workspace
+#[derive(Debug, Error)]
+pub enum NamingError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Naming after a container property requires a moved container")]
+    NoContainer,
+    #[error("Could not find container {0}")]
+    ContainerNotFound(i64),
+    #[error("Container {0} has no {1}")]
+    MissingProperty(i64, &'static str),
+    #[error("Invalid name_pattern in config: {0}")]
+    InvalidPattern(#[from] regex::Error),
+    #[error("Name \"{0}\" does not match name_pattern \"{1}\" from config")]
+    PatternMismatch(String, String),
+    #[error("Could not read name from stdin: {0}")]
+    Stdin(std::io::Error),
+    #[error("Name read from stdin is empty")]
+    EmptyStdin,
+    #[error("Could not run prompt: {0}")]
+    Prompt(std::io::Error),
+    #[error("Prompt returned no name")]
+    PromptCancelled,
+    #[error("Could not read window manager config \"{0}\": {1}")]
+    ReadWmConfig(String, std::io::Error),
+    #[error("Could not read wordlist \"{0}\": {1}")]
+    ReadWordlist(PathBuf, std::io::Error),
+    #[error("Wordlist \"{0}\" is empty")]
+    EmptyWordlist(PathBuf),
+    #[error("No unused name fits into {0} characters")]
+    NoUniqueTruncation(usize),
+    #[error("No unused name found for template \"{0}\"")]
+    NoUnusedRandomName(String),
+}
+
+/// Ways to name the new workspace
+#[derive(clap::Args, Clone, Debug)]
+pub struct NameSelection {
+    /// Name of the new workspace.
+    ///
+    /// `-` reads the name from standard input
+    #[clap(short, long, env = "I3IW_NAME")]
+    name: Option<String>,
+
+    /// Ask for the name of the new workspace using `dmenu`, `rofi` or a custom command.
+    ///
+    /// The name is read from the standard output of the command
+    #[clap(
+        long, env = "I3IW_NAME_PROMPT",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "dmenu",
+        value_name = "dmenu|rofi|CMD",
+        conflicts_with = "name"
+    )]
+    name_prompt: Option<String>,
+
+    /// Name the new workspace after a property of the moved container
+    #[clap(long, env = "I3IW_NAME_FROM", value_enum, conflicts_with_all = ["name", "name_prompt"])]
+    name_from: Option<NameSource>,
+
+    /// Template for the name of the new workspace.
+    ///
+    /// Placeholders: `{index}` position of the new workspace on its output, `{output}` name of the output,
+    /// `{pivot}` name of the pivot, `{date}` current date, `{random}` random name
+    #[clap(
+        long,
+        env = "I3IW_NAME_TEMPLATE",
+        conflicts_with_all = ["name", "name_prompt", "name_from"]
+    )]
+    name_template: Option<String>,
+
+    /// How to generate the name of the new workspace, if no name or template is given
+    #[clap(
+        long,
+        env = "I3IW_NAME_SCHEME",
+        value_enum,
+        conflicts_with_all = ["name", "name_prompt", "name_from", "name_template"]
+    )]
+    name_scheme: Option<NameScheme>,
+
+    /// Which workspaces are considered when generating numbered names
+    #[clap(long, env = "I3IW_NUMBERING", value_enum, default_value_t = Numbering::PerOutput)]
+    numbering: Numbering,
+
+    /// Word lists used for random names
+    #[clap(long, env = "I3IW_NAME_THEME", value_enum)]
+    name_theme: Option<Theme>,
+
+    /// File with one word per line, replacing the left words of random names
+    #[clap(long, env = "I3IW_WORDLIST_LEFT")]
+    wordlist_left: Option<PathBuf>,
+
+    /// File with one word per line, replacing the right words of random names
+    #[clap(long, env = "I3IW_WORDLIST_RIGHT")]
+    wordlist_right: Option<PathBuf>,
+
+    /// Seed for random names, making them reproducible
+    #[clap(long, env = "I3IW_NAME_SEED")]
+    name_seed: Option<u64>,
+
+    /// Prefix added to the name of the new workspace
+    #[clap(long, env = "I3IW_NAME_PREFIX")]
+    name_prefix: Option<String>,
+
+    /// Suffix added to the name of the new workspace
+    #[clap(long, env = "I3IW_NAME_SUFFIX")]
+    name_suffix: Option<String>,
+
+    /// Do not generate names of workspaces bound to keys in the i3 or sway config
+    #[clap(long, env = "I3IW_AVOID_BOUND_NAMES")]
+    avoid_bound_names: bool,
+
+    /// Truncate the name of the new workspace to at most this many characters, including the icon but excluding prefix and suffix.
+    ///
+    /// At least 3, to fit a character and a `~2` counter telling apart names equal after truncation
+    #[clap(
+        long, env = "I3IW_MAX_NAME_LENGTH",
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(3..)
+    )]
+    max_name_length: Option<usize>,
+}
+
+/// Schemes to generate workspace names
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum NameScheme {
+    /// Random name in the style of docker container names
+    Random,
+    /// Number following the highest workspace number
+    Sequential,
+    /// Current date, followed by a letter to make it unique
+    Datetime,
+    /// Smallest number not used by a workspace
+    NextFreeNumber,
+    /// Letters `a`, `b`, …, `z`, `aa`, … following the last letter name
+    Letters,
+}
+
+/// Container properties to name workspaces after
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+pub enum NameSource {
+    /// X11 window class
+    WindowClass,
+    /// Window title
+    WindowTitle,
+    /// Wayland application id
+    AppId,
+    /// Name of the working directory of the window's process
+    Cwd,
+}
+
+/// Information about the insertion, used to fill the placeholders of name templates
+pub struct NamingContext<'a> {
+    pub pivot: &'a Pivot,
+    pub before: bool,
+    /// Container moved to the new workspace
+    pub container: Option<i64>,
+    /// Names of the workspaces inserted before this one in the same invocation
+    pub inserted: &'a [String],
+}
+
+/// Generator of random names, combining words of two lists
+struct RandomNames {
+    left: Vec<String>,
+    right: Vec<String>,
+    rng: StdRng,
+}
+
+impl RandomNames {
+    /// Load the wordlist files, using the lists of `theme` for missing files
+    ///
+    /// If `seed` is given, the generated names are reproducible
+    fn load(
+        theme: Theme,
+        left: Option<&Path>,
+        right: Option<&Path>,
+        seed: Option<u64>,
+    ) -> Result<Self, NamingError> {
+        let (theme_left, theme_right) = theme.words();
+        Ok(Self {
+            left: left.map_or_else(|| Ok(to_owned_words(theme_left)), read_wordlist)?,
+            right: right.map_or_else(|| Ok(to_owned_words(theme_right)), read_wordlist)?,
+            rng: seed.map_or_else(StdRng::from_entropy, StdRng::seed_from_u64),
+        })
+    }
+
+    /// Generate a random name from the words
+    fn generate(&mut self) -> String {
+        docker_name::random_name_from(&self.left, &self.right, &mut self.rng)
+    }
+}
+
+fn to_owned_words(words: &[&str]) -> Vec<String> {
+    words.iter().map(|x| (*x).to_owned()).collect()
+}
+
+/// Read a wordlist file, skipping empty lines and `#` comments
+fn read_wordlist(path: &Path) -> Result<Vec<String>, NamingError> {
+    let words = std::fs::read_to_string(path)
+        .map_err(|e| NamingError::ReadWordlist(path.to_owned(), e))?
+        .lines()
+        .map(str::trim)
+        .filter(|x| !x.is_empty() && !x.starts_with('#'))
+        .map(ToOwned::to_owned)
+        .collect::<Vec<_>>();
+    if words.is_empty() {
+        return Err(NamingError::EmptyWordlist(path.to_owned()));
+    }
+    Ok(words)
+}
+
+impl NameSelection {
+    /// Name the new workspace `name`, as if given to `--name`
+    pub fn set_name(&mut self, name: String) {
+        self.name = Some(name);
+    }
+
+    /// Scope of workspace numbers chosen by `--numbering`
+    pub const fn numbering(&self) -> Numbering {
+        self.numbering
+    }
+
+    /// Get the name of the new workspace
+    ///
+    /// Uses the explicit name, then the name template, and finally generates a name using the name scheme.
+    /// The icon of the moved container is added to the name, which is then truncated to the maximum length,
+    /// the name prefix and suffix are added to it,
+    /// and the result is checked against the name pattern of the config.
+    pub fn resolve(
+        mut self,
+        conn: &mut Connection,
+        config: &Config,
+        context: &NamingContext,
+    ) -> Result<String, NamingError> {
+        let prefix = self.name_prefix.take().unwrap_or_default();
+        let suffix = self.name_suffix.take().unwrap_or_default();
+        let max_length = self.max_name_length;
+
+        let workspaces = get_workspaces(conn)?;
+        let mut existing_names = workspaces
+            .iter()
+            .map(|x| x.name.clone())
+            .chain(context.inserted.iter().cloned())
+            .collect::<Vec<_>>();
+        if self.avoid_bound_names {
+            let config_path = conn.get_version()?.loaded_config_file_name;
+            let wm_config = std::fs::read_to_string(&config_path)
+                .map_err(|e| NamingError::ReadWmConfig(config_path, e))?;
+            existing_names.extend(bound_names::bound_workspace_names(&wm_config));
+        }
+        // Names which would collide with existing workspaces after decoration
+        let taken_names = existing_names
+            .iter()
+            .filter_map(|x| x.strip_prefix(&prefix)?.strip_suffix(&suffix))
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+
+        let icon = context
+            .container
+            .filter(|_| !config.icons.is_empty())
+            .map(|container| container_icon(conn, container, &config.icons))
+            .transpose()?
+            .flatten();
+        let icon_prefix = icon.map(|icon| format!("{icon} ")).unwrap_or_default();
+        // Names which would collide with existing workspaces after adding the icon
+        let taken_without_icon = taken_names
+            .iter()
+            .filter_map(|x| x.strip_prefix(&icon_prefix))
+            .map(ToOwned::to_owned)
+            .collect::<Vec<_>>();
+
+        let name =
+            self.undecorated_name(conn, config, context, &workspaces, &taken_without_icon)?;
+        let mut name = format!("{icon_prefix}{name}");
+        if let Some(max_length) = max_length {
+            name = truncate_unique(&name, max_length, &taken_names)?;
+        }
+        let name = format!("{prefix}{name}{suffix}");
+        if let Some(pattern) = &config.name_pattern {
+            if !Regex::new(pattern)?.is_match(&name) {
+                return Err(NamingError::PatternMismatch(name, pattern.clone()));
+            }
+        }
+        Ok(name)
+    }
+
+    /// Get the name of the new workspace, without prefix and suffix
+    ///
+    /// Generated names are not contained in `taken_names`
+    fn undecorated_name(
+        self,
+        conn: &mut Connection,
+        config: &Config,
+        context: &NamingContext,
+        workspaces: &[Workspace],
+        taken_names: &[String],
+    ) -> Result<String, NamingError> {
+        if let Some(name) = self.name {
+            if name == "-" {
+                return read_stdin_name();
+            }
+            return Ok(name);
+        }
+        if let Some(prompt) = self.name_prompt {
+            return prompt_name(&prompt);
+        }
+        if let Some(source) = self.name_from {
+            let container = context.container.ok_or(NamingError::NoContainer)?;
+            return container_property(conn, container, source);
+        }
+        // The wordlists are only read if a random name is generated
+        let random_names = || {
+            RandomNames::load(
+                self.name_theme.or(config.name_theme).unwrap_or_default(),
+                self.wordlist_left
+                    .as_deref()
+                    .or(config.wordlist_left.as_deref()),
+                self.wordlist_right
+                    .as_deref()
+                    .or(config.wordlist_right.as_deref()),
+                self.name_seed,
+            )
+        };
+        if let Some(template) = &self.name_template {
+            return expand_template(template, context, workspaces, taken_names, random_names);
+        }
+        let scheme = match (self.name_scheme, &config.name_template) {
+            (Some(scheme), _) => scheme,
+            (None, Some(template)) => {
+                return expand_template(template, context, workspaces, taken_names, random_names)
+            }
+            (None, None) => config.name_scheme.unwrap_or(NameScheme::Random),
+        };
+        match scheme {
+            NameScheme::Sequential => Ok(sequential_name(
+                workspaces,
+                context.pivot.output.as_deref(),
+                self.numbering,
+                taken_names,
+            )),
+            NameScheme::NextFreeNumber => Ok(next_free_number(
+                workspaces,
+                context.pivot.output.as_deref(),
+                self.numbering,
+                taken_names,
+            )),
+            NameScheme::Datetime => Ok(datetime_name(taken_names)),
+            NameScheme::Letters => Ok(letters_name(
+                workspaces,
+                context.pivot.output.as_deref(),
+                self.numbering,
+                taken_names,
+            )),
+            NameScheme::Random => Ok(generate_new_workspace_name(
+                taken_names,
+                &mut random_names()?,
+            )),
+        }
+    }
+}
+
+/// Truncate `name` to at most `max_length` graphemes
+///
+/// If the truncated name is contained in `taken_names`, its end is replaced by a counter.
+/// Fails if no counter leaves room for a character of the name
+fn truncate_unique(
+    name: &str,
+    max_length: usize,
+    taken_names: &[String],
+) -> Result<String, NamingError> {
+    let truncate = |length: usize| name.graphemes(true).take(length).collect::<String>();
+    if max_length == 0 {
+        return Err(NamingError::NoUniqueTruncation(max_length));
+    }
+    let truncated = truncate(max_length);
+    if !taken_names.contains(&truncated) {
+        return Ok(truncated);
+    }
+    let mut counter = 2_usize;
+    loop {
+        let counter_suffix = format!("~{counter}");
+        let length = max_length
+            .checked_sub(counter_suffix.len())
+            .filter(|x| *x > 0)
+            .ok_or(NamingError::NoUniqueTruncation(max_length))?;
+        let candidate = truncate(length) + &counter_suffix;
+        if !taken_names.contains(&candidate) {
+            return Ok(candidate);
+        }
+        counter += 1;
+    }
+}
+
+/// Read the name from standard input, without the trailing newline
+fn read_stdin_name() -> Result<String, NamingError> {
+    let input = std::io::read_to_string(std::io::stdin()).map_err(NamingError::Stdin)?;
+    let name = input.trim_end_matches(['\n', '\r']);
+    if name.is_empty() {
+        return Err(NamingError::EmptyStdin);
+    }
+    Ok(name.to_owned())
+}
+
+/// Ask the user for a name using `prompt`
+///
+/// `prompt` is either `dmenu`, `rofi`, or a shell command printing the name
+fn prompt_name(prompt: &str) -> Result<String, NamingError> {
+    const PROMPT_TEXT: &str = "Workspace name";
+    let mut command = match prompt {
+        "dmenu" => {
+            let mut command = Command::new("dmenu");
+            command.args(["-p", PROMPT_TEXT]);
+            command
+        }
+        "rofi" => {
+            let mut command = Command::new("rofi");
+            command.args(["-dmenu", "-p", PROMPT_TEXT]);
+            command
+        }
+        custom => {
+            let mut command = Command::new("sh");
+            command.args(["-c", custom]);
+            command
+        }
+    };
+    let output = command
+        .stdin(Stdio::null())
+        .output()
+        .map_err(NamingError::Prompt)?;
+    let name = String::from_utf8_lossy(&output.stdout).trim().to_owned();
+    if !output.status.success() || name.is_empty() {
+        return Err(NamingError::PromptCancelled);
+    }
+    Ok(name)
+}
+
+/// Get a property of `container`, made suitable as a workspace name
+fn container_property(
+    conn: &mut Connection,
+    container: i64,
+    source: NameSource,
+) -> Result<String, NamingError> {
+    let tree = raw_tree::get_tree(conn)?;
+    let node = tree
+        .find(container)
+        .ok_or(NamingError::ContainerNotFound(container))?;
+    let window_properties = node.window_properties.as_ref();
+    let (property, description) = match source {
+        NameSource::WindowClass => (
+            window_properties.and_then(|x| x.class.clone()),
+            "window class",
+        ),
+        NameSource::WindowTitle => (
+            node.name
+                .clone()
+                .or_else(|| window_properties.and_then(|x| x.title.clone())),
+            "window title",
+        ),
+        NameSource::AppId => (node.app_id.clone(), "app id"),
+        NameSource::Cwd => (
+            process::window_pid(node)
+                .and_then(process::working_directory)
+                .and_then(|x| x.file_name().map(|x| x.to_string_lossy().into_owned())),
+            "working directory",
+        ),
+    };
+    property
+        .map(|x| sanitize(&x))
+        .filter(|x| !x.is_empty())
+        .ok_or(NamingError::MissingProperty(container, description))
+}
+
+/// Find the icon for the window class or app id of `container`
+///
+/// `icons` maps lowercase window classes or app ids to icons
+fn container_icon(
+    conn: &mut Connection,
+    container: i64,
+    icons: &HashMap<String, String>,
+) -> Result<Option<String>, NamingError> {
+    let tree = raw_tree::get_tree(conn)?;
+    let node = tree
+        .find(container)
+        .ok_or(NamingError::ContainerNotFound(container))?;
+    Ok(node
+        .class()
+        .and_then(|x| icons.get(&x.to_lowercase()))
+        .cloned())
+}
+
+/// Remove characters which break i3 commands or bar layouts from `name`
+fn sanitize(name: &str) -> String {
+    name.split(|c: char| c.is_whitespace() || c.is_control())
+        .filter(|x| !x.is_empty())
+        .collect::<Vec<_>>()
+        .join(" ")
+        .replace('"', "'")
+}
+
+/// Fill the placeholders of `template`
+///
+/// If the template contains `{random}`, the wordlists are loaded by `random_names`,
+/// and a name not contained in `taken_names` is searched, giving up after 10 attempts
+fn expand_template(
+    template: &str,
+    context: &NamingContext,
+    workspaces: &[Workspace],
+    taken_names: &[String],
+    random_names: impl FnOnce() -> Result<RandomNames, NamingError>,
+) -> Result<String, NamingError> {
+    let output = context.pivot.output.as_deref().unwrap_or_default();
+    let pivot_index = workspaces
+        .iter()
+        .filter(|x| x.output == output)
+        .position(|x| x.name == context.pivot.name)
+        .unwrap_or_default();
+    let index = if context.before {
+        pivot_index + 1
+    } else {
+        pivot_index + 2
+    } + context.inserted.len();
+    let partially_expanded = template
+        .replace("{index}", &index.to_string())
+        .replace("{output}", output)
+        .replace("{pivot}", &context.pivot.name)
+        .replace(
+            "{date}",
+            &chrono::Local::now().format("%Y-%m-%d").to_string(),
+        );
+
+    if !partially_expanded.contains("{random}") {
+        return Ok(partially_expanded);
+    }
+
+    let mut random_names = random_names()?;
+    (0..10)
+        .map(|_| partially_expanded.replace("{random}", &random_names.generate()))
+        .find(|name| !taken_names.contains(name))
+        .ok_or_else(|| NamingError::NoUnusedRandomName(template.to_owned()))
+}
+
+/// Get the number following the highest workspace number
+///
+/// With per-output numbering only the workspaces on `output` are considered.
+/// Numbers used by any workspace, or contained in `taken_names`, are skipped, to keep workspace numbers unique.
+fn sequential_name(
+    workspaces: &[Workspace],
+    output: Option<&str>,
+    numbering: Numbering,
+    taken_names: &[String],
+) -> String {
+    let highest = workspaces
+        .iter()
+        .filter(|x| numbering == Numbering::Global || Some(x.output.as_str()) == output)
+        .map(|x| x.num)
+        .max()
+        .unwrap_or_default()
+        .max(0);
+    let mut next = highest + 1;
+    while workspaces.iter().any(|x| x.num == next) || taken_names.contains(&next.to_string()) {
+        next += 1;
+    }
+    next.to_string()
+}
+
+/// Get the smallest positive number not used by a workspace
+///
+/// With per-output numbering only the numbers of workspaces on `output` are considered,
+/// but the number must not be the name of any workspace or contained in `taken_names`.
+fn next_free_number(
+    workspaces: &[Workspace],
+    output: Option<&str>,
+    numbering: Numbering,
+    taken_names: &[String],
+) -> String {
+    let is_used = |number: i32| {
+        let name = number.to_string();
+        taken_names.contains(&name)
+            || workspaces.iter().any(|x| {
+                x.name == name
+                    || (x.num == number
+                        && (numbering == Numbering::Global || Some(x.output.as_str()) == output))
+            })
+    };
+    let mut number = 1;
+    while is_used(number) {
+        number += 1;
+    }
+    number.to_string()
+}
+
+/// Get the current date, followed by the first letter sequence not making it collide with `taken_names`
+fn datetime_name(taken_names: &[String]) -> String {
+    let date = chrono::Local::now().format("%Y-%m-%d");
+    let mut index = 0;
+    loop {
+        let name = format!("{date}-{}", letter_sequence(index));
+        if !taken_names.contains(&name) {
+            return name;
+        }
+        index += 1;
+    }
+}
+
+/// Get the `index`th element of the sequence `a`, `b`, …, `z`, `aa`, `ab`, …
+fn letter_sequence(mut index: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.extend(('a'..='z').nth(index % 26));
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    letters.iter().rev().collect()
+}
+
+/// Get the position of `name` in the sequence of `letter_sequence`
+///
+/// Returns `None` if `name` does not consist of lowercase ASCII letters only
+fn letter_index(name: &str) -> Option<usize> {
+    if name.is_empty() {
+        return None;
+    }
+    name.chars()
+        .try_fold(0_usize, |index, letter| {
+            let digit = ('a'..='z').position(|x| x == letter)?;
+            index.checked_mul(26)?.checked_add(digit + 1)
+        })
+        .map(|x| x - 1)
+}
+
+/// Get the letter sequence following the last letter name
+///
+/// With per-output numbering only the workspaces on `output` are considered.
+/// Names contained in `taken_names` are skipped.
+fn letters_name(
+    workspaces: &[Workspace],
+    output: Option<&str>,
+    numbering: Numbering,
+    taken_names: &[String],
+) -> String {
+    let mut index = workspaces
+        .iter()
+        .filter(|x| numbering == Numbering::Global || Some(x.output.as_str()) == output)
+        .filter_map(|x| letter_index(&x.name))
+        .max()
+        .map_or(0, |x| x + 1);
+    while taken_names.contains(&letter_sequence(index)) {
+        index += 1;
+    }
+    letter_sequence(index)
+}
+
+/// Generate a random name, make sure no workspace with this name exists already
+fn generate_new_workspace_name(
+    workspace_names: &[String],
+    random_names: &mut RandomNames,
+) -> String {
+    for _ in 0..10 {
+        let new_name = random_names.generate();
+        if !workspace_names.iter().any(|x| x == &new_name) {
+            return new_name;
+        }
+    }
+    guaranteed_workspace_name(workspace_names)
+}
+
+/// Fallback workspace name if for some reason no name can be generated
+fn guaranteed_workspace_name(workspace_names: &[String]) -> String {
+    const NEW_WORKSPACE_PREFIX: &str = "new_workspace_";
+    let largest_index = workspace_names
+        .iter()
+        .filter_map(|x| {
+            if !x.starts_with(NEW_WORKSPACE_PREFIX) {
+                return None;
+            }
+            let index = &x[NEW_WORKSPACE_PREFIX.len()..];
+            index.parse::<u32>().ok()
+        })
+        .max()
+        .unwrap_or(0);
+    format!("{NEW_WORKSPACE_PREFIX}{}", largest_index + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use rand::{rngs::StdRng, SeedableRng};
+
+    use super::{expand_template, truncate_unique, NamingContext, NamingError, RandomNames};
+    use crate::util::Pivot;
+
+    fn taken(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| (*x).to_owned()).collect()
+    }
+
+    #[test]
+    fn truncates() -> Result<(), NamingError> {
+        assert_eq!(truncate_unique("firefox", 4, &[])?, "fire");
+        assert_eq!(truncate_unique("vim", 4, &[])?, "vim");
+        assert_eq!(truncate_unique("näive", 3, &[])?, "näi");
+        Ok(())
+    }
+
+    #[test]
+    fn counts_taken_names() -> Result<(), NamingError> {
+        assert_eq!(truncate_unique("firefox", 4, &taken(&["fire"]))?, "fi~2");
+        assert_eq!(
+            truncate_unique("firefox", 4, &taken(&["fire", "fi~2"]))?,
+            "fi~3"
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn stays_within_length() -> Result<(), NamingError> {
+        let mut names = taken(&["abc"]);
+        for _ in 0..20 {
+            let name = truncate_unique("abcdef", 5, &names)?;
+            assert!(name.chars().count() <= 5, "{name}");
+            assert!(!names.contains(&name));
+            names.push(name);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn rejects_too_short_lengths() {
+        assert!(matches!(
+            truncate_unique("abc", 0, &[]),
+            Err(NamingError::NoUniqueTruncation(0))
+        ));
+        assert!(matches!(
+            truncate_unique("abc", 2, &taken(&["ab"])),
+            Err(NamingError::NoUniqueTruncation(2))
+        ));
+        let names = (2..10)
+            .map(|x| format!("a~{x}"))
+            .chain(["abc".to_owned()])
+            .collect::<Vec<_>>();
+        assert!(matches!(
+            truncate_unique("abcdef", 3, &names),
+            Err(NamingError::NoUniqueTruncation(3))
+        ));
+    }
+
+    #[test]
+    fn loads_wordlists_only_for_random() -> Result<(), NamingError> {
+        let pivot = Pivot {
+            name: "a".to_owned(),
+            output: Some("A".to_owned()),
+        };
+        let context = NamingContext {
+            pivot: &pivot,
+            before: false,
+            container: None,
+            inserted: &[],
+        };
+        let missing = || Err(NamingError::EmptyWordlist(PathBuf::from("missing")));
+        assert_eq!(
+            expand_template("{pivot}-{output}", &context, &[], &[], missing)?,
+            "a-A"
+        );
+        assert!(matches!(
+            expand_template("{random}", &context, &[], &[], missing),
+            Err(NamingError::EmptyWordlist(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn fails_if_random_names_are_taken() -> Result<(), NamingError> {
+        let pivot = Pivot {
+            name: "a".to_owned(),
+            output: Some("A".to_owned()),
+        };
+        let context = NamingContext {
+            pivot: &pivot,
+            before: false,
+            container: None,
+            inserted: &[],
+        };
+        let single = || {
+            Ok(RandomNames {
+                left: taken(&["silly"]),
+                right: taken(&["fox"]),
+                rng: StdRng::seed_from_u64(0),
+            })
+        };
+        let name = expand_template("ws-{random}", &context, &[], &[], single)?;
+        assert!(name.starts_with("ws-"), "{name}");
+        assert!(matches!(
+            expand_template("ws-{random}", &context, &[], &[name], single),
+            Err(NamingError::NoUnusedRandomName(template)) if template == "ws-{random}"
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn truncates_with_icon() -> Result<(), NamingError> {
+        assert_eq!(truncate_unique("🦊 firefox", 5, &[])?, "🦊 fir");
+        assert_eq!(
+            truncate_unique("🦊 firefox", 5, &taken(&["🦊 fir"]))?,
+            "🦊 f~2"
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,537 @@
+//! Support for the `number: label` naming convention of workspaces
+use i3ipc::reply::Workspace;
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    util::{collision_free, get_workspaces, run_commands, CommandError, Pivot, WorkspaceRename},
+};
+
+/// Errors for `numbered_insertion`
+#[derive(Debug, Error)]
+pub enum NumberedError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Pivot \"{0}\" is not numbered")]
+    UnnumberedPivot(String),
+    #[error("Workspace \"{0}\" already exists")]
+    NameCollision(String),
+    #[error("Could not rename workspaces: {0}")]
+    Command(#[from] CommandError),
+}
+
+/// Scope of workspace numbers
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Numbering {
+    /// Numbers are counted separately for each output
+    PerOutput,
+    /// Numbers are counted across all outputs
+    Global,
+}
+
+/// Options to number the new workspace
+#[derive(clap::Args, Debug)]
+pub struct NumberedSelection {
+    /// Follow the `number: label` convention for workspace names.
+    ///
+    /// The new workspace is numbered according to its position,
+    /// and the numbers of following workspaces are shifted to make room for it
+    #[clap(long, env = "I3IW_NUMBERED")]
+    numbered: bool,
+
+    /// Like `--numbered`, but shift the numbers of all following workspaces.
+    ///
+    /// With per-output numbering only the workspaces on the output of the pivot are shifted.
+    ///
+    /// Keeps the order of numbers consistent with the order of workspaces, even across gaps in the numbering
+    #[clap(long, env = "I3IW_RENUMBER", conflicts_with = "numbered")]
+    renumber: bool,
+}
+
+impl NumberedSelection {
+    /// Number the new workspaces named `names`, if numbering was requested
+    ///
+    /// Returns the final names, and the renames shifting the following workspaces
+    pub fn resolve(
+        &self,
+        conn: &mut Connection,
+        pivot: &Pivot,
+        before: bool,
+        numbering: Numbering,
+        names: Vec<String>,
+    ) -> Result<(Vec<String>, Vec<WorkspaceRename>), NumberedError> {
+        if self.numbered || self.renumber {
+            let shift_scope = self.renumber.then_some(numbering);
+            numbered_insertion(conn, pivot, before, shift_scope, &names)
+        } else {
+            Ok((names, Vec::new()))
+        }
+    }
+}
+
+/// Options of the `renumber` subcommand
+#[derive(clap::Args, Debug)]
+pub struct RenumberArgs {
+    /// Output whose workspaces are renumbered with per-output numbering, defaults to the focused output
+    #[clap(long)]
+    output: Option<String>,
+
+    /// Renumber the workspaces of one output, or of all outputs as one sequence
+    #[clap(long, value_enum, default_value_t = Numbering::PerOutput)]
+    numbering: Numbering,
+}
+
+impl RenumberArgs {
+    /// Number the numbered workspaces densely from 1, in their current order
+    pub fn run(self, conn: &mut Connection, focused_output: &str) -> Result<(), NumberedError> {
+        let workspaces = get_workspaces(conn)?;
+        let output = (self.numbering == Numbering::PerOutput)
+            .then(|| self.output.unwrap_or_else(|| focused_output.to_owned()));
+        let renames = renumber_renames(&workspaces, output.as_deref());
+        apply_renames(conn, &workspaces, renames)
+    }
+}
+
+/// Renames numbering the numbered workspaces on `output`, or on all outputs, densely from 1 in their order
+fn renumber_renames(workspaces: &[Workspace], output: Option<&str>) -> Vec<WorkspaceRename> {
+    workspaces
+        .iter()
+        .filter(|x| output.is_none_or(|output| x.output == output))
+        .filter_map(|x| NumberedName::parse(&x.name).map(|parsed| (x, parsed)))
+        .zip(1..)
+        .map(|((workspace, parsed), number)| WorkspaceRename {
+            from: workspace.name.clone(),
+            to: parsed.with_number(number),
+        })
+        .collect()
+}
+
+/// Options of the `compact` subcommand
+#[derive(clap::Args, Debug)]
+pub struct CompactArgs {
+    /// Only close the gaps between the workspaces on this output, with per-output numbering
+    #[clap(long)]
+    output: Option<String>,
+
+    /// Close the gaps on each output separately, or across all outputs
+    #[clap(long, value_enum, default_value_t = Numbering::PerOutput)]
+    numbering: Numbering,
+}
+
+impl CompactArgs {
+    /// Close the gaps between workspace numbers, keeping their order
+    pub fn run(self, conn: &mut Connection) -> Result<(), NumberedError> {
+        compact(conn, self.numbering, self.output.as_deref())
+    }
+}
+
+/// Close the gaps between the numbers of workspaces
+///
+/// With per-output numbering each output is compacted separately, or only `output` if given
+pub fn compact(
+    conn: &mut Connection,
+    numbering: Numbering,
+    output: Option<&str>,
+) -> Result<(), NumberedError> {
+    let workspaces = get_workspaces(conn)?;
+    let renames = match numbering {
+        Numbering::Global => compact_renames(&workspaces.iter().collect::<Vec<_>>()),
+        Numbering::PerOutput => {
+            let mut outputs = workspaces
+                .iter()
+                .map(|x| x.output.as_str())
+                .filter(|x| output.is_none_or(|output| *x == output))
+                .collect::<Vec<_>>();
+            outputs.sort_unstable();
+            outputs.dedup();
+            outputs
+                .iter()
+                .flat_map(|output| {
+                    compact_renames(
+                        &workspaces
+                            .iter()
+                            .filter(|x| x.output == *output)
+                            .collect::<Vec<_>>(),
+                    )
+                })
+                .collect()
+        }
+    };
+    apply_renames(conn, &workspaces, renames)
+}
+
+/// Renames closing the gaps between the numbers of `workspaces`, keeping their order
+fn compact_renames(workspaces: &[&Workspace]) -> Vec<WorkspaceRename> {
+    let numbered = workspaces
+        .iter()
+        .filter_map(|x| NumberedName::parse(&x.name).map(|parsed| (x, parsed)))
+        .collect::<Vec<_>>();
+    let mut numbers = numbered
+        .iter()
+        .map(|(_, parsed)| parsed.number)
+        .collect::<Vec<_>>();
+    numbers.sort_unstable();
+    numbers.dedup();
+    numbered
+        .iter()
+        .filter_map(|(workspace, parsed)| {
+            let position = numbers.binary_search(&parsed.number).ok()?;
+            Some(WorkspaceRename {
+                from: workspace.name.clone(),
+                to: parsed.with_number(i32::try_from(position).ok()? + 1),
+            })
+        })
+        .collect()
+}
+
+/// Check that no rename targets the name of one of `workspaces` which is not renamed
+fn check_collisions(
+    workspaces: &[Workspace],
+    renames: &[WorkspaceRename],
+) -> Result<(), NumberedError> {
+    let collision = renames.iter().find(|rename| {
+        workspaces.iter().any(|x| x.name == rename.to)
+            && !renames.iter().any(|other| other.from == rename.to)
+    });
+    collision.map_or(Ok(()), |rename| {
+        Err(NumberedError::NameCollision(rename.to.clone()))
+    })
+}
+
+/// Rename workspaces, checking that no rename targets the name of a workspace which is not renamed
+fn apply_renames(
+    conn: &mut Connection,
+    workspaces: &[Workspace],
+    renames: Vec<WorkspaceRename>,
+) -> Result<(), NumberedError> {
+    check_collisions(workspaces, &renames)?;
+    let commands = collision_free(renames)
+        .iter()
+        .map(WorkspaceRename::command)
+        .collect::<Vec<_>>();
+    Ok(run_commands(conn, &commands)?)
+}
+
+/// Workspace name following the `number: label` convention
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct NumberedName<'a> {
+    pub number: i32,
+    pub label: Option<&'a str>,
+}
+
+impl<'a> NumberedName<'a> {
+    /// Parse names of the form `number` or `number: label`
+    pub fn parse(name: &'a str) -> Option<Self> {
+        let (number, label) = name
+            .split_once(':')
+            .map_or((name, None), |(number, label)| {
+                (number, Some(label.trim_start()))
+            });
+        Some(Self {
+            number: number.parse().ok().filter(|x| *x >= 0)?,
+            label,
+        })
+    }
+
+    /// Format the name with a different number
+    pub fn with_number(&self, number: i32) -> String {
+        self.label
+            .map_or_else(|| number.to_string(), |label| format!("{number}: {label}"))
+    }
+}
+
+/// Renames making room for `count` new workspaces numbered from `number` on
+///
+/// Shifts the workspace numbers colliding with the new ones up by `count`, and in turn those colliding with the shifted ones,
+/// the highest number first so no intermediate names collide
+fn make_room(workspaces: &[Workspace], number: i32, count: i32) -> Vec<WorkspaceRename> {
+    let mut numbered = workspaces
+        .iter()
+        .filter_map(|x| NumberedName::parse(&x.name).map(|parsed| (x, parsed)))
+        .filter(|(_, parsed)| parsed.number >= number)
+        .collect::<Vec<_>>();
+    numbered.sort_by_key(|(_, parsed)| parsed.number);
+    // Numbers below this have to be freed
+    let mut occupied_end = number + count;
+    let mut renames = Vec::new();
+    for (workspace, parsed) in numbered {
+        if parsed.number >= occupied_end {
+            break;
+        }
+        renames.push(WorkspaceRename {
+            from: workspace.name.clone(),
+            to: parsed.with_number(parsed.number + count),
+        });
+        occupied_end = occupied_end.max(parsed.number + count + 1);
+    }
+    renames.reverse();
+    renames
+}
+
+/// Renames shifting all numbered workspaces on `output` with a number of at least `number` up by `count`
+///
+/// The highest number is shifted first so no intermediate names collide.
+/// Workspaces on other outputs are not shifted, so their names may still collide
+fn shift_following(
+    workspaces: &[Workspace],
+    output: Option<&str>,
+    number: i32,
+    count: i32,
+) -> Vec<WorkspaceRename> {
+    let mut following = workspaces
+        .iter()
+        .filter(|x| output.is_none_or(|output| x.output == output))
+        .filter_map(|x| NumberedName::parse(&x.name).map(|parsed| (x, parsed)))
+        .filter(|(_, parsed)| parsed.number >= number)
+        .collect::<Vec<_>>();
+    following.sort_by_key(|(_, parsed)| std::cmp::Reverse(parsed.number));
+    following
+        .iter()
+        .map(|(workspace, parsed)| WorkspaceRename {
+            from: workspace.name.clone(),
+            to: parsed.with_number(parsed.number + count),
+        })
+        .collect()
+}
+
+/// Number the new workspaces according to their position next to `pivot`
+///
+/// Returns the numbered names, and the renames shifting the following workspaces to make room for them.
+/// With a `shift_scope` all following workspaces in this scope are shifted,
+/// otherwise only those whose numbers would collide.
+/// Fails if a shifted name is taken by a workspace which is not shifted, e.g. on another output
+fn numbered_insertion(
+    conn: &mut Connection,
+    pivot: &Pivot,
+    before: bool,
+    shift_scope: Option<Numbering>,
+    labels: &[String],
+) -> Result<(Vec<String>, Vec<WorkspaceRename>), NumberedError> {
+    let pivot_number = NumberedName::parse(&pivot.name)
+        .ok_or_else(|| NumberedError::UnnumberedPivot(pivot.name.clone()))?
+        .number;
+    let number = if before {
+        pivot_number
+    } else {
+        pivot_number + 1
+    };
+    let workspaces = get_workspaces(conn)?;
+    let names = labels
+        .iter()
+        .zip(number..)
+        .map(|(label, number)| {
+            NumberedName {
+                number,
+                label: Some(label),
+            }
+            .with_number(number)
+        })
+        .collect::<Vec<_>>();
+    let count = i32::try_from(labels.len()).unwrap_or(i32::MAX);
+    let renames = match shift_scope {
+        Some(Numbering::PerOutput) => {
+            shift_following(&workspaces, pivot.output.as_deref(), number, count)
+        }
+        Some(Numbering::Global) => shift_following(&workspaces, None, number, count),
+        None => make_room(&workspaces, number, count),
+    };
+    check_collisions(&workspaces, &renames)?;
+    Ok((names, renames))
+}
+
+#[cfg(test)]
+mod tests {
+    use i3ipc::reply::Workspace;
+
+    use super::{
+        check_collisions, compact_renames, make_room, renumber_renames, shift_following,
+        NumberedError, NumberedName,
+    };
+    use crate::util::WorkspaceRename;
+
+    fn workspace(name: &str, output: &str) -> Workspace {
+        Workspace {
+            num: -1,
+            name: name.to_owned(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            rect: (0, 0, 0, 0),
+            output: output.to_owned(),
+        }
+    }
+
+    fn pairs(renames: &[WorkspaceRename]) -> Vec<(&str, &str)> {
+        renames
+            .iter()
+            .map(|x| (x.from.as_str(), x.to.as_str()))
+            .collect()
+    }
+
+    #[test]
+    fn parses_numbered_names() {
+        let parsed = |number, label| Some(NumberedName { number, label });
+        assert_eq!(NumberedName::parse("3"), parsed(3, None));
+        assert_eq!(NumberedName::parse("3:mail"), parsed(3, Some("mail")));
+        assert_eq!(NumberedName::parse("3: mail"), parsed(3, Some("mail")));
+        assert_eq!(NumberedName::parse("3:"), parsed(3, Some("")));
+        assert_eq!(NumberedName::parse("mail"), None);
+        assert_eq!(NumberedName::parse("mail: 3"), None);
+        assert_eq!(NumberedName::parse("-3"), None);
+        assert_eq!(NumberedName::parse("-3: mail"), None);
+        assert_eq!(NumberedName::parse("2147483648"), None);
+        assert_eq!(NumberedName::parse("2147483648: mail"), None);
+    }
+
+    #[test]
+    fn formats_with_number() {
+        assert_eq!(
+            NumberedName::parse("3")
+                .map(|x| x.with_number(4))
+                .as_deref(),
+            Some("4")
+        );
+        assert_eq!(
+            NumberedName::parse("3:mail")
+                .map(|x| x.with_number(4))
+                .as_deref(),
+            Some("4: mail")
+        );
+    }
+
+    #[test]
+    fn makes_room_for_colliding_numbers() {
+        let workspaces = vec![
+            workspace("1", "A"),
+            workspace("2: mail", "A"),
+            workspace("3", "B"),
+            workspace("5: chat", "A"),
+            workspace("web", "A"),
+        ];
+        assert_eq!(
+            pairs(&make_room(&workspaces, 2, 1)),
+            [("3", "4"), ("2: mail", "3: mail")]
+        );
+        assert_eq!(
+            pairs(&make_room(&workspaces, 2, 2)),
+            [("5: chat", "7: chat"), ("3", "5"), ("2: mail", "4: mail")]
+        );
+        assert_eq!(pairs(&make_room(&workspaces, 4, 1)), []);
+        assert_eq!(pairs(&make_room(&workspaces, 6, 3)), []);
+    }
+
+    #[test]
+    fn renumbers_in_order() {
+        let workspaces = vec![
+            workspace("3: mail", "A"),
+            workspace("web", "A"),
+            workspace("7", "B"),
+            workspace("1: chat", "A"),
+            workspace("9", "A"),
+        ];
+        assert_eq!(
+            pairs(&renumber_renames(&workspaces, Some("A"))),
+            [("3: mail", "1: mail"), ("1: chat", "2: chat"), ("9", "3")]
+        );
+        assert_eq!(
+            pairs(&renumber_renames(&workspaces, None)),
+            [
+                ("3: mail", "1: mail"),
+                ("7", "2"),
+                ("1: chat", "3: chat"),
+                ("9", "4")
+            ]
+        );
+        assert_eq!(pairs(&renumber_renames(&workspaces, Some("C"))), []);
+    }
+
+    #[test]
+    fn renumbering_one_output_collides_with_others() {
+        let workspaces = vec![
+            workspace("4", "A"),
+            workspace("1", "B"),
+            workspace("2: mail", "A"),
+        ];
+        let renames = renumber_renames(&workspaces, Some("A"));
+        assert!(matches!(
+            check_collisions(&workspaces, &renames),
+            Err(NumberedError::NameCollision(name)) if name == "1"
+        ));
+    }
+
+    #[test]
+    fn renumbering_swapped_numbers_is_no_collision() {
+        let workspaces = vec![workspace("2", "A"), workspace("1", "A")];
+        let renames = renumber_renames(&workspaces, None);
+        assert_eq!(pairs(&renames), [("2", "1"), ("1", "2")]);
+        assert!(check_collisions(&workspaces, &renames).is_ok());
+    }
+
+    #[test]
+    fn compacting_closes_gaps() {
+        let workspaces = [
+            workspace("1", "A"),
+            workspace("2: mail", "A"),
+            workspace("web", "A"),
+            workspace("7", "A"),
+            workspace("5: chat", "A"),
+        ];
+        assert_eq!(
+            pairs(&compact_renames(&workspaces.iter().collect::<Vec<_>>())),
+            [
+                ("1", "1"),
+                ("2: mail", "2: mail"),
+                ("7", "4"),
+                ("5: chat", "3: chat")
+            ]
+        );
+    }
+
+    #[test]
+    fn compacting_keeps_equal_numbers_equal() {
+        let workspaces = [
+            workspace("3: mail", "A"),
+            workspace("3: chat", "B"),
+            workspace("6", "A"),
+        ];
+        assert_eq!(
+            pairs(&compact_renames(&workspaces.iter().collect::<Vec<_>>())),
+            [("3: mail", "1: mail"), ("3: chat", "1: chat"), ("6", "2")]
+        );
+    }
+
+    #[test]
+    fn compacting_one_output_ignores_the_others() {
+        let workspaces = vec![
+            workspace("2", "A"),
+            workspace("1", "B"),
+            workspace("4: mail", "A"),
+        ];
+        let output = workspaces
+            .iter()
+            .filter(|x| x.output == "A")
+            .collect::<Vec<_>>();
+        let renames = compact_renames(&output);
+        assert_eq!(pairs(&renames), [("2", "1"), ("4: mail", "2: mail")]);
+        assert!(matches!(
+            check_collisions(&workspaces, &renames),
+            Err(NumberedError::NameCollision(name)) if name == "1"
+        ));
+    }
+
+    #[test]
+    fn shifting_into_another_output_collides() {
+        let workspaces = vec![
+            workspace("1", "A"),
+            workspace("2: mail", "A"),
+            workspace("3: mail", "B"),
+        ];
+        let renames = shift_following(&workspaces, Some("A"), 2, 1);
+        assert!(matches!(
+            check_collisions(&workspaces, &renames),
+            Err(NumberedError::NameCollision(name)) if name == "3: mail"
+        ));
+        let renames = shift_following(&workspaces, None, 2, 1);
+        assert!(check_collisions(&workspaces, &renames).is_ok());
+    }
+}
@@ -0,0 +1,649 @@
+//! Resolve the pivot workspace from the different ways of selecting it
+use std::cmp::Reverse;
+
+use i3ipc::reply::Workspace;
+use regex::{Regex, RegexBuilder};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    raw_tree::{self, RawNode, RawTreeError},
+    util::{get_workspaces, Pivot},
+};
+
+/// Group of the arguments naming the pivot, required by `--fuzzy-pivot`
+pub const PIVOT_NAME: &str = "pivot_name";
+
+/// Errors while resolving the pivot workspace
+#[derive(Debug, Error)]
+pub enum PivotError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+    #[error("Invalid regex: {0}")]
+    InvalidRegex(#[from] regex::Error),
+    #[error("No workspace matches {0}")]
+    NoMatch(String),
+    #[error("Multiple workspaces match {0}: {1}")]
+    Ambiguous(String, String),
+}
+
+/// Ways to select the pivot workspace, and how to match it
+///
+/// The options naming the pivot belong to the group `pivot_name`, which other ways of naming it can join
+#[derive(clap::Args, Debug)]
+#[clap(group(clap::ArgGroup::new(PIVOT_NAME).multiple(true)))]
+pub struct PivotSelection {
+    #[clap(flatten)]
+    selector: PivotSelector,
+
+    /// If no workspace is named exactly like the pivot, fuzzily match the pivot against the workspace names.
+    ///
+    /// Errors if multiple workspaces match equally well
+    #[clap(long, env = "I3IW_FUZZY_PIVOT", requires = PIVOT_NAME)]
+    fuzzy_pivot: bool,
+
+    /// Match the pivot name or regex case-insensitively.
+    ///
+    /// Errors if multiple workspaces match
+    #[clap(long, env = "I3IW_IGNORE_CASE")]
+    ignore_case: bool,
+
+    /// Output containing the pivot.
+    ///
+    /// Needed if workspaces with the same name exist on multiple outputs
+    #[clap(long, env = "I3IW_ON_OUTPUT")]
+    on_output: Option<String>,
+
+    /// Shift the pivot by this many workspaces on its output.
+    ///
+    /// Negative values shift towards the first workspace, the result is clamped to the output's workspaces
+    #[clap(
+        long,
+        env = "I3IW_OFFSET",
+        allow_negative_numbers = true,
+        default_value_t = 0
+    )]
+    offset: i32,
+
+    /// Which workspace is the pivot, if no pivot is given
+    #[clap(long, env = "I3IW_PIVOT_SCOPE", value_enum, default_value_t = PivotScope::Focused)]
+    pivot_scope: PivotScope,
+
+    /// Output used by `--pivot-scope visible-on-output`
+    #[clap(
+        long,
+        env = "I3IW_SCOPE_OUTPUT",
+        required_if_eq("pivot_scope", "visible-on-output")
+    )]
+    scope_output: Option<String>,
+}
+
+/// Implicit pivot, if no pivot is given
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum PivotScope {
+    /// The globally focused workspace
+    Focused,
+    /// The visible workspace on the output given by `--scope-output`
+    VisibleOnOutput,
+}
+
+/// Mutually exclusive selectors of the pivot workspace
+#[derive(clap::Args, Debug)]
+#[group(multiple = false)]
+struct PivotSelector {
+    /// Workspace before or after which the new workspace is inserted.
+    ///
+    /// If no pivot given, using the workspace selected by `--pivot-scope`.
+    /// The keywords `next`, `prev`, `first` and `last` select a workspace on the focused output,
+    /// relative to the focused workspace.
+    /// `last-N` or `end-N` select the Nth workspace before the last one on the focused output.
+    /// `back_and_forth` selects the previously focused workspace on the focused output.
+    /// A workspace named exactly like a keyword is selected instead of the keyword
+    #[clap(short, long, env = "I3IW_PIVOT", group = PIVOT_NAME)]
+    pivot: Option<String>,
+
+    /// Number of the workspace before or after which the new workspace is inserted.
+    ///
+    /// Alternative to `--pivot`, matches the workspace number instead of the full name
+    #[clap(long, env = "I3IW_PIVOT_NUM")]
+    pivot_num: Option<i32>,
+
+    /// Regular expression matching the workspace before or after which the new workspace is inserted.
+    ///
+    /// Alternative to `--pivot`, exactly one workspace must match
+    #[clap(long, env = "I3IW_PIVOT_REGEX")]
+    pivot_regex: Option<Regex>,
+
+    /// Output whose visible workspace is the one before or after which the new workspace is inserted.
+    ///
+    /// Alternative to `--pivot`
+    #[clap(long, env = "I3IW_PIVOT_OUTPUT")]
+    pivot_output: Option<String>,
+
+    /// Mark of a container, whose workspace is the one before or after which the new workspace is inserted.
+    ///
+    /// Alternative to `--pivot`
+    #[clap(long, env = "I3IW_PIVOT_MARK")]
+    pivot_mark: Option<String>,
+}
+
+impl PivotSelection {
+    /// Select `pivot` by name, as if given to `--pivot`
+    pub fn set_pivot(&mut self, pivot: String) {
+        self.selector.pivot = Some(pivot);
+    }
+
+    /// Only accept pivots on `output`, unless an output was already given
+    pub fn restrict_output(&mut self, output: String) {
+        self.on_output.get_or_insert(output);
+    }
+
+    /// Get the name of the selected pivot workspace
+    ///
+    /// Falls back to the workspace selected by the pivot scope if no pivot was selected
+    pub fn resolve(
+        self,
+        conn: &mut Connection,
+        focused_workspace: String,
+    ) -> Result<Pivot, PivotError> {
+        let selected =
+            self.selector
+                .resolve(conn, &focused_workspace, self.fuzzy_pivot, self.ignore_case)?;
+        let pivot = match (selected, self.pivot_scope, self.scope_output) {
+            (Some(pivot), _, _) => pivot,
+            (None, PivotScope::VisibleOnOutput, Some(output)) => visible_on_output(conn, &output)?,
+            (None, _, _) => focused_workspace,
+        };
+        let pivot = qualify_output(conn, pivot, self.on_output)?;
+        if self.offset == 0 {
+            return Ok(pivot);
+        }
+        shift_workspace(&get_workspaces(conn)?, &pivot, self.offset)
+    }
+}
+
+impl PivotSelector {
+    /// Get the name of the selected pivot workspace, without applying an offset
+    ///
+    /// Returns `None` if no pivot was selected
+    fn resolve(
+        self,
+        conn: &mut Connection,
+        focused_workspace: &str,
+        fuzzy: bool,
+        ignore_case: bool,
+    ) -> Result<Option<String>, PivotError> {
+        if let Some(num) = self.pivot_num {
+            return unique_workspace(conn, &format!("number {num}"), |x| x.num == num).map(Some);
+        }
+        if let Some(mut regex) = self.pivot_regex {
+            if ignore_case {
+                regex = RegexBuilder::new(regex.as_str())
+                    .case_insensitive(true)
+                    .build()?;
+            }
+            return unique_workspace(conn, &format!("regex \"{regex}\""), |x| {
+                regex.is_match(&x.name)
+            })
+            .map(Some);
+        }
+        if let Some(output) = self.pivot_output {
+            return visible_on_output(conn, &output).map(Some);
+        }
+        if let Some(mark) = self.pivot_mark {
+            return workspace_with_mark(&raw_tree::get_tree(conn)?, &mark, None)
+                .ok_or_else(|| PivotError::NoMatch(format!("mark \"{mark}\"")))
+                .map(Some);
+        }
+        let Some(name) = self.pivot else {
+            return Ok(None);
+        };
+        let relative = RelativePivot::parse(&name);
+        if relative.is_some() || name == "back_and_forth" {
+            let workspaces = get_workspaces(conn)?;
+            if workspaces.iter().any(|x| x.name == name) {
+                return Ok(Some(name));
+            }
+            return relative
+                .map_or_else(
+                    || previous_workspace(conn),
+                    |relative| relative.resolve(&workspaces, &name, focused_workspace),
+                )
+                .map(Some);
+        }
+        if fuzzy {
+            fuzzy_workspace(conn, &name).map(Some)
+        } else if ignore_case {
+            let lowercase_name = name.to_lowercase();
+            unique_workspace(conn, &format!("case-insensitive name \"{name}\""), |x| {
+                x.name.to_lowercase() == lowercase_name
+            })
+            .map(Some)
+        } else {
+            Ok(Some(name))
+        }
+    }
+}
+
+/// Output receiving a workspace inserted at the boundary between two outputs
+#[derive(clap::ValueEnum, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "kebab-case")]
+pub enum BoundaryOutput {
+    /// The output of the pivot
+    Pivot,
+    /// The neighboring output, in the global order of workspaces
+    Neighbor,
+}
+
+/// Move an insertion next to `pivot` at the boundary of its output onto the neighboring output
+///
+/// In the global order of workspaces the position after the last workspace of an output
+/// is also the position before the first workspace of the next output, and vice versa.
+/// Returns the pivot and whether to insert before it, unchanged if the position is not at a boundary
+pub fn cross_boundary(
+    conn: &mut Connection,
+    pivot: Pivot,
+    before: bool,
+    boundary_output: BoundaryOutput,
+) -> Result<(Pivot, bool), PivotError> {
+    if matches!(boundary_output, BoundaryOutput::Pivot) {
+        return Ok((pivot, before));
+    }
+    let workspaces = get_workspaces(conn)?;
+    let Some(index) = workspaces
+        .iter()
+        .position(|x| x.name == pivot.name && pivot.output.as_ref().is_none_or(|o| &x.output == o))
+    else {
+        return Ok((pivot, before));
+    };
+    let neighbor = if before {
+        index.checked_sub(1).and_then(|x| workspaces.get(x))
+    } else {
+        workspaces.get(index + 1)
+    };
+    match neighbor {
+        Some(neighbor) if pivot.output.as_ref() != Some(&neighbor.output) => Ok((
+            Pivot {
+                name: neighbor.name.clone(),
+                output: Some(neighbor.output.clone()),
+            },
+            !before,
+        )),
+        _ => Ok((pivot, before)),
+    }
+}
+
+/// Find the name of the visible workspace on `output`
+fn visible_on_output(conn: &mut Connection, output: &str) -> Result<String, PivotError> {
+    unique_workspace(
+        conn,
+        &format!("visible workspace on output \"{output}\""),
+        |x| x.visible && x.output == output,
+    )
+}
+
+/// Find the output containing the workspace `name`
+///
+/// Errors if the workspace exists on multiple outputs and `output` does not tell them apart
+fn qualify_output(
+    conn: &mut Connection,
+    name: String,
+    output: Option<String>,
+) -> Result<Pivot, PivotError> {
+    let workspaces = get_workspaces(conn)?;
+    let candidates = workspaces
+        .iter()
+        .filter(|x| x.name == name && output.as_ref().is_none_or(|o| &x.output == o))
+        .collect::<Vec<_>>();
+    match (candidates.as_slice(), output) {
+        ([], Some(output)) => Err(PivotError::NoMatch(format!(
+            "name \"{name}\" on output \"{output}\""
+        ))),
+        ([], None) => Ok(Pivot { name, output: None }),
+        ([single], _) => Ok(Pivot {
+            output: Some(single.output.clone()),
+            name,
+        }),
+        _ => Err(PivotError::Ambiguous(
+            format!("name \"{name}\""),
+            candidate_list(&candidates),
+        )),
+    }
+}
+
+/// Find the workspace `offset` positions away from `pivot` on its output among `workspaces`
+///
+/// The position is clamped to the workspaces of the output
+fn shift_workspace(
+    workspaces: &[Workspace],
+    pivot: &Pivot,
+    offset: i32,
+) -> Result<Pivot, PivotError> {
+    let output = pivot
+        .output
+        .as_ref()
+        .ok_or_else(|| PivotError::NoMatch(format!("workspace \"{}\"", pivot.name)))?;
+    let output_workspaces = workspaces
+        .iter()
+        .filter(|x| &x.output == output)
+        .collect::<Vec<_>>();
+    let pivot_index = output_workspaces
+        .iter()
+        .position(|x| x.name == pivot.name)
+        .unwrap_or_default();
+    let distance = offset.unsigned_abs() as usize;
+    let index = if offset < 0 {
+        pivot_index.saturating_sub(distance)
+    } else {
+        pivot_index
+            .saturating_add(distance)
+            .min(output_workspaces.len().saturating_sub(1))
+    };
+    output_workspaces
+        .get(index)
+        .map(|x| Pivot {
+            name: x.name.clone(),
+            output: Some(x.output.clone()),
+        })
+        .ok_or_else(|| PivotError::NoMatch(format!("offset {offset} from \"{}\"", pivot.name)))
+}
+
+/// Pivot keywords, selecting a workspace relative to the focused workspace on its output
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+enum RelativePivot {
+    Next,
+    Prev,
+    First,
+    /// Workspace with the given distance to the last workspace
+    FromEnd(usize),
+}
+
+impl RelativePivot {
+    fn parse(keyword: &str) -> Option<Self> {
+        match keyword {
+            "next" => Some(Self::Next),
+            "prev" => Some(Self::Prev),
+            "first" => Some(Self::First),
+            "last" | "end" => Some(Self::FromEnd(0)),
+            _ => ["last-", "end-"]
+                .iter()
+                .find_map(|prefix| keyword.strip_prefix(prefix))
+                .and_then(|distance| distance.parse().ok())
+                .map(Self::FromEnd),
+        }
+    }
+
+    /// Find the name of the workspace among `workspaces` selected by this keyword
+    ///
+    /// Like i3 does for `workspace next_on_output`, `next` and `prev` wrap around
+    fn resolve(
+        self,
+        workspaces: &[Workspace],
+        keyword: &str,
+        focused_workspace: &str,
+    ) -> Result<String, PivotError> {
+        let focused = workspaces
+            .iter()
+            .find(|x| x.name == focused_workspace)
+            .ok_or_else(|| {
+                PivotError::NoMatch(format!("focused workspace \"{focused_workspace}\""))
+            })?;
+        let output_workspaces = workspaces
+            .iter()
+            .filter(|x| x.output == focused.output)
+            .collect::<Vec<_>>();
+        let focused_index = output_workspaces
+            .iter()
+            .position(|x| x.name == focused.name)
+            .unwrap_or_default();
+        let count = output_workspaces.len();
+        let index = match self {
+            Self::Next => Some((focused_index + 1) % count),
+            Self::Prev => Some((focused_index + count - 1) % count),
+            Self::First => Some(0),
+            Self::FromEnd(distance) => count.checked_sub(distance + 1),
+        };
+        index
+            .and_then(|x| output_workspaces.get(x))
+            .map(|x| x.name.clone())
+            .ok_or_else(|| PivotError::NoMatch(format!("pivot keyword \"{keyword}\"")))
+    }
+}
+
+/// Get the most recently focused child of `node`
+fn focused_child(node: &RawNode) -> Option<&RawNode> {
+    node.focus
+        .first()
+        .and_then(|id| node.nodes.iter().find(|x| x.id == *id))
+}
+
+/// Find the name of the previously focused workspace on the focused output
+///
+/// Uses the focus history of the output, so switching between outputs is not considered
+fn previous_workspace(conn: &mut Connection) -> Result<String, PivotError> {
+    let tree = raw_tree::get_tree(conn)?;
+    let description = "previously focused workspace".to_owned();
+    let output = focused_child(&tree).ok_or_else(|| PivotError::NoMatch(description.clone()))?;
+    // In i3 the workspaces are inside the `content` container of the output, in sway they are direct children
+    let container = output
+        .nodes
+        .iter()
+        .find(|x| x.name.as_deref() == Some("content"))
+        .unwrap_or(output);
+    container
+        .focus
+        .get(1)
+        .and_then(|id| container.nodes.iter().find(|x| x.id == *id))
+        .and_then(|x| x.name.clone())
+        .ok_or(PivotError::NoMatch(description))
+}
+
+/// Find the name of the workspace containing the container marked with `mark`
+///
+/// `workspace` is the name of the workspace containing `node`, if known
+fn workspace_with_mark(node: &RawNode, mark: &str, workspace: Option<&str>) -> Option<String> {
+    let workspace = if node.nodetype == "workspace" {
+        node.name.as_deref()
+    } else {
+        workspace
+    };
+    if node.marks.iter().any(|x| x == mark) {
+        return workspace.map(ToOwned::to_owned);
+    }
+    node.children()
+        .find_map(|child| workspace_with_mark(child, mark, workspace))
+}
+
+/// Score how well `query` matches the workspace name `name`, higher is better
+///
+/// Returns `None` if the characters of `query` do not appear in order in `name`.
+/// Substring matches are preferred, then shorter names.
+fn fuzzy_score(query: &str, name: &str) -> Option<(bool, Reverse<usize>)> {
+    let query = query.to_lowercase();
+    let name = name.to_lowercase();
+    let mut name_chars = name.chars();
+    let is_subsequence = query.chars().all(|q| name_chars.any(|n| n == q));
+    is_subsequence.then(|| (name.contains(&query), Reverse(name.len())))
+}
+
+/// Find the name of the workspace best matching `query`
+///
+/// An exact match always wins, otherwise the best fuzzy match is chosen
+fn fuzzy_workspace(conn: &mut Connection, query: &str) -> Result<String, PivotError> {
+    let workspaces = get_workspaces(conn)?;
+    if workspaces.iter().any(|x| x.name == query) {
+        return Ok(query.to_owned());
+    }
+    let scored = workspaces
+        .iter()
+        .filter_map(|x| fuzzy_score(query, &x.name).map(|score| (score, x)))
+        .collect::<Vec<_>>();
+    let description = format!("fuzzy pattern \"{query}\"");
+    let best_score = scored
+        .iter()
+        .map(|(score, _)| *score)
+        .max()
+        .ok_or_else(|| PivotError::NoMatch(description.clone()))?;
+    let best = scored
+        .iter()
+        .filter(|(score, _)| *score == best_score)
+        .map(|(_, x)| *x)
+        .collect::<Vec<_>>();
+    match best.as_slice() {
+        [single] => Ok(single.name.clone()),
+        _ => Err(PivotError::Ambiguous(description, candidate_list(&best))),
+    }
+}
+
+/// Format the names of candidate workspaces for error messages
+fn candidate_list(candidates: &[&Workspace]) -> String {
+    candidates
+        .iter()
+        .map(|x| format!("\"{}\" on \"{}\"", x.name, x.output))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Find the name of the only workspace satisfying `predicate`
+///
+/// `description` describes the predicate for error messages
+fn unique_workspace(
+    conn: &mut Connection,
+    description: &str,
+    predicate: impl Fn(&Workspace) -> bool,
+) -> Result<String, PivotError> {
+    let workspaces = get_workspaces(conn)?;
+    let candidates = workspaces
+        .iter()
+        .filter(|x| predicate(x))
+        .collect::<Vec<_>>();
+    match candidates.as_slice() {
+        [] => Err(PivotError::NoMatch(description.to_owned())),
+        [single] => Ok(single.name.clone()),
+        _ => Err(PivotError::Ambiguous(
+            description.to_owned(),
+            candidate_list(&candidates),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use i3ipc::reply::Workspace;
+
+    use super::{shift_workspace, PivotError, RelativePivot};
+    use crate::util::Pivot;
+
+    fn workspace(name: &str, output: &str) -> Workspace {
+        Workspace {
+            num: -1,
+            name: name.to_owned(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            rect: (0, 0, 0, 0),
+            output: output.to_owned(),
+        }
+    }
+
+    fn workspaces() -> Vec<Workspace> {
+        vec![
+            workspace("a", "A"),
+            workspace("b", "A"),
+            workspace("c", "A"),
+            workspace("d", "B"),
+        ]
+    }
+
+    fn pivot(name: &str, output: Option<&str>) -> Pivot {
+        Pivot {
+            name: name.to_owned(),
+            output: output.map(ToOwned::to_owned),
+        }
+    }
+
+    #[test]
+    fn parses_keywords() {
+        assert_eq!(RelativePivot::parse("next"), Some(RelativePivot::Next));
+        assert_eq!(RelativePivot::parse("prev"), Some(RelativePivot::Prev));
+        assert_eq!(RelativePivot::parse("first"), Some(RelativePivot::First));
+        assert_eq!(
+            RelativePivot::parse("last"),
+            Some(RelativePivot::FromEnd(0))
+        );
+        assert_eq!(RelativePivot::parse("end"), Some(RelativePivot::FromEnd(0)));
+        assert_eq!(
+            RelativePivot::parse("last-2"),
+            Some(RelativePivot::FromEnd(2))
+        );
+        assert_eq!(
+            RelativePivot::parse("end-1"),
+            Some(RelativePivot::FromEnd(1))
+        );
+        for name in ["Next", "previous", "last-", "last--1", "end-x", "web"] {
+            assert_eq!(RelativePivot::parse(name), None, "{name}");
+        }
+    }
+
+    #[test]
+    fn resolves_keywords_on_the_focused_output() -> Result<(), PivotError> {
+        let workspaces = workspaces();
+        let resolve = |keyword: &str, focused: &str| {
+            RelativePivot::parse(keyword)
+                .ok_or_else(|| PivotError::NoMatch(keyword.to_owned()))?
+                .resolve(&workspaces, keyword, focused)
+        };
+        assert_eq!(resolve("next", "b")?, "c");
+        assert_eq!(resolve("prev", "b")?, "a");
+        assert_eq!(resolve("first", "c")?, "a");
+        assert_eq!(resolve("last", "a")?, "c");
+        assert_eq!(resolve("last-1", "a")?, "b");
+        assert_eq!(resolve("next", "d")?, "d");
+        assert!(matches!(
+            resolve("last-3", "a"),
+            Err(PivotError::NoMatch(_))
+        ));
+        assert!(matches!(resolve("next", "x"), Err(PivotError::NoMatch(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn next_and_prev_wrap_around() -> Result<(), PivotError> {
+        let workspaces = workspaces();
+        assert_eq!(RelativePivot::Next.resolve(&workspaces, "next", "c")?, "a");
+        assert_eq!(RelativePivot::Prev.resolve(&workspaces, "prev", "a")?, "c");
+        Ok(())
+    }
+
+    #[test]
+    fn shifts_within_the_output() -> Result<(), PivotError> {
+        let workspaces = workspaces();
+        let shifted = |name, offset| {
+            shift_workspace(&workspaces, &pivot(name, Some("A")), offset).map(|x| x.name)
+        };
+        assert_eq!(shifted("b", 1)?, "c");
+        assert_eq!(shifted("b", -1)?, "a");
+        assert_eq!(shifted("b", 5)?, "c");
+        assert_eq!(shifted("b", -5)?, "a");
+        assert_eq!(
+            shift_workspace(&workspaces, &pivot("d", Some("B")), 1)?,
+            pivot("d", Some("B"))
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn shifting_needs_the_output() {
+        assert!(matches!(
+            shift_workspace(&workspaces(), &pivot("new", None), 1),
+            Err(PivotError::NoMatch(_))
+        ));
+        assert!(matches!(
+            shift_workspace(&workspaces(), &pivot("a", Some("C")), 1),
+            Err(PivotError::NoMatch(_))
+        ));
+    }
+}
@@ -0,0 +1,213 @@
+//! Commands of an insertion, computed before any of them is run
+use i3ipc::reply::{CommandOutcome, Workspace};
+
+use crate::{
+    backend::{BackendError, WmBackend},
+    util::{renamed, WorkspaceRename},
+};
+
+/// Commands inserting workspaces, and the workspaces expected on the output of the insertion afterwards
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Plan {
+    /// Commands, to be sent in a single message
+    pub commands: Vec<String>,
+    /// Output the workspaces are inserted on
+    pub output: String,
+    /// Names of the workspaces on `output` after the insertion, in order
+    pub workspaces: Vec<String>,
+}
+
+impl Plan {
+    /// Plan `commands` inserting `names` at `index` between the workspaces `current` of `output`
+    ///
+    /// `renames` are applied to the current workspaces, the new names are not expected elsewhere on the output
+    pub(crate) fn new<'a>(
+        commands: Vec<String>,
+        output: &str,
+        current: impl IntoIterator<Item = &'a str>,
+        index: usize,
+        names: &[String],
+        renames: &[WorkspaceRename],
+    ) -> Self {
+        let current = current
+            .into_iter()
+            .map(|x| renamed(renames, x).to_owned())
+            .filter(|x| !names.contains(x))
+            .collect::<Vec<_>>();
+        let index = index.min(current.len());
+        let mut workspaces = current;
+        let following = workspaces.split_off(index);
+        workspaces.extend(names.iter().cloned());
+        workspaces.extend(following);
+        let plan = Self {
+            commands,
+            output: output.to_owned(),
+            workspaces,
+        };
+        log::debug!(
+            "Expected workspaces on {}: {}",
+            plan.output,
+            plan.workspaces.join(", ")
+        );
+        plan
+    }
+
+    /// Send the commands in a single message, returning the outcome of each of them
+    ///
+    /// # Errors
+    ///
+    /// Fails if the commands can not be sent, not if they fail to run
+    pub fn run(&self, backend: &mut impl WmBackend) -> Result<Vec<CommandOutcome>, BackendError> {
+        if self.commands.is_empty() {
+            return Ok(Vec::new());
+        }
+        backend.run_commands(&self.commands)
+    }
+
+    /// Expected position of the workspace `name` on `output`, counted from 1
+    #[must_use]
+    pub fn index(&self, name: &str) -> Option<usize> {
+        self.workspaces
+            .iter()
+            .position(|x| x == name)
+            .map(|x| x + 1)
+    }
+
+    /// Check if the workspaces of `output` among `workspaces` are the expected ones, in the expected order
+    #[must_use]
+    pub fn verify(&self, workspaces: &[Workspace]) -> bool {
+        workspaces
+            .iter()
+            .filter(|x| x.output == self.output)
+            .map(|x| &x.name)
+            .eq(self.workspaces.iter())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use i3ipc::reply::Workspace;
+
+    use super::Plan;
+    use crate::{
+        backend::WmBackend,
+        insert_workspace_rename, insert_workspace_swap,
+        mock::MockBackend,
+        util::{ContainerChanges, InsertionDestination, Pivot, WorkspaceRename},
+    };
+
+    fn names(names: &[&str]) -> Vec<String> {
+        names.iter().map(|x| (*x).to_owned()).collect()
+    }
+
+    fn plan(current: &[&str], index: usize, new: &[&str], renames: &[WorkspaceRename]) -> Plan {
+        Plan::new(
+            Vec::new(),
+            "A",
+            current.iter().copied(),
+            index,
+            &names(new),
+            renames,
+        )
+    }
+
+    fn workspace(name: &str, output: &str) -> Workspace {
+        Workspace {
+            num: -1,
+            name: name.to_owned(),
+            visible: false,
+            focused: false,
+            urgent: false,
+            rect: (0, 0, 0, 0),
+            output: output.to_owned(),
+        }
+    }
+
+    #[test]
+    fn inserts_at_index() {
+        let plan = plan(&["a", "b", "c"], 1, &["x", "y"], &[]);
+        assert_eq!(plan.workspaces, ["a", "x", "y", "b", "c"]);
+        assert_eq!(plan.index("y"), Some(3));
+        assert_eq!(plan.index("z"), None);
+    }
+
+    #[test]
+    fn clamps_index() {
+        let plan = plan(&["a", "b"], 5, &["x"], &[]);
+        assert_eq!(plan.workspaces, ["a", "b", "x"]);
+    }
+
+    #[test]
+    fn applies_renames() {
+        let renames = [WorkspaceRename {
+            from: "b".to_owned(),
+            to: "2: b".to_owned(),
+        }];
+        let plan = plan(&["a", "b", "c"], 2, &["x"], &renames);
+        assert_eq!(plan.workspaces, ["a", "2: b", "x", "c"]);
+    }
+
+    #[test]
+    fn moves_existing_names() {
+        let plan = plan(&["a", "x", "b"], 2, &["x"], &[]);
+        assert_eq!(plan.workspaces, ["a", "b", "x"]);
+    }
+
+    #[test]
+    fn verifies_own_output() {
+        let plan = plan(&["a", "b"], 1, &["x"], &[]);
+        let mut workspaces = vec![
+            workspace("a", "A"),
+            workspace("c", "B"),
+            workspace("x", "A"),
+            workspace("b", "A"),
+        ];
+        assert!(plan.verify(&workspaces));
+        workspaces.swap(2, 3);
+        assert!(!plan.verify(&workspaces));
+        workspaces.pop();
+        assert!(!plan.verify(&workspaces));
+    }
+
+    #[test]
+    fn run_plans_match() -> Result<(), Box<dyn std::error::Error>> {
+        let outputs: &[(&str, &[&str])] = &[("A", &["a", "b", "c"]), ("B", &["d", "e"])];
+        for pivot in ["a", "b", "c", "d", "e"] {
+            for before in [false, true] {
+                for containers in [&[][..], &[1]] {
+                    let destination = InsertionDestination::new(
+                        Pivot {
+                            name: pivot.to_owned(),
+                            output: None,
+                        },
+                        before,
+                    );
+                    let new = names(&["x"]);
+
+                    let mut backend = MockBackend::with_outputs(outputs);
+                    let plan = insert_workspace_rename::insert_workspace(
+                        &mut backend,
+                        &destination,
+                        &new,
+                        containers,
+                        ContainerChanges::default(),
+                        &[],
+                    )?;
+                    assert!(plan.verify(&backend.get_workspaces()?), "i3 {plan:?}");
+
+                    let mut backend = MockBackend::with_outputs(outputs);
+                    let plan = insert_workspace_swap::insert_workspace(
+                        &mut backend,
+                        &destination,
+                        &new,
+                        containers,
+                        ContainerChanges::default(),
+                        &[],
+                    )?;
+                    assert!(plan.verify(&backend.get_workspaces()?), "sway {plan:?}");
+                }
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,55 @@
+//! Inspect the processes owning windows
+use std::{path::PathBuf, process::Command};
+
+use crate::raw_tree::RawNode;
+
+/// Get the process id owning the window of `node`
+///
+/// Sway reports it in the tree, for X11 windows `_NET_WM_PID` is queried using `xprop`
+pub fn window_pid(node: &RawNode) -> Option<u32> {
+    if let Some(pid) = node.pid {
+        return Some(pid);
+    }
+    let output = Command::new("xprop")
+        .args(["-id", &node.window?.to_string(), "_NET_WM_PID"])
+        .output()
+        .ok()?;
+    // Output looks like `_NET_WM_PID(CARDINAL) = 1234`
+    String::from_utf8_lossy(&output.stdout)
+        .rsplit_once('=')?
+        .1
+        .trim()
+        .parse()
+        .ok()
+}
+
+/// Get the most recently started child process of `pid`
+fn last_child(pid: u32) -> Option<u32> {
+    std::fs::read_to_string(format!("/proc/{pid}/task/{pid}/children"))
+        .ok()?
+        .split_whitespace()
+        .last()?
+        .parse()
+        .ok()
+}
+
+/// Get the working directory of the innermost descendant of `pid`
+///
+/// For terminals this is the working directory of the shell or the program running in it
+pub fn working_directory(mut pid: u32) -> Option<PathBuf> {
+    while let Some(child) = last_child(pid) {
+        pid = child;
+    }
+    std::fs::read_link(format!("/proc/{pid}/cwd")).ok()
+}
+
+/// Get the command line `pid` was started with
+pub fn command_line(pid: u32) -> Option<Vec<String>> {
+    let cmdline = std::fs::read(format!("/proc/{pid}/cmdline")).ok()?;
+    let arguments = cmdline
+        .split(|x| *x == 0)
+        .filter(|x| !x.is_empty())
+        .map(|x| String::from_utf8_lossy(x).into_owned())
+        .collect::<Vec<_>>();
+    (!arguments.is_empty()).then_some(arguments)
+}
@@ -0,0 +1,238 @@
+//! Subcommands printing the state of the workspaces, for scripts
+use std::io::Write;
+
+use i3ipc::reply::Workspace;
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{connection::Connection, numbered::NumberedName, util::get_workspaces};
+
+/// Errors of the query subcommands
+#[derive(Debug, Error)]
+pub enum QueryError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not write JSON: {0}")]
+    Json(#[from] serde_json::Error),
+    #[error("Could not write output: {0}")]
+    Write(#[from] std::io::Error),
+    #[error("Could not find workspace \"{0}\"")]
+    NoWorkspace(String),
+}
+
+/// Workspace and its position, as printed by `list`
+#[derive(Serialize, Debug)]
+struct WorkspaceEntry<'a> {
+    name: &'a str,
+    output: &'a str,
+    /// Position of the workspace on its output, counted from 1
+    index: usize,
+    /// Number of `number: label` names
+    number: Option<i32>,
+    visible: bool,
+    focused: bool,
+}
+
+impl<'a> WorkspaceEntry<'a> {
+    /// Entries for `workspaces`, in their order
+    fn all(workspaces: &'a [Workspace]) -> Vec<Self> {
+        workspaces
+            .iter()
+            .map(|workspace| WorkspaceEntry {
+                name: &workspace.name,
+                output: &workspace.output,
+                index: workspaces
+                    .iter()
+                    .filter(|x| x.output == workspace.output)
+                    .position(|x| x.name == workspace.name)
+                    .unwrap_or_default()
+                    + 1,
+                number: NumberedName::parse(&workspace.name).map(|x| x.number),
+                visible: workspace.visible,
+                focused: workspace.focused,
+            })
+            .collect()
+    }
+
+    /// Tab separated line of output, index, number, flags and name
+    ///
+    /// Missing numbers and flags are printed as `-`, the name comes last as it may contain tabs
+    fn line(&self) -> String {
+        let flags = [(self.focused, "focused"), (self.visible, "visible")]
+            .into_iter()
+            .filter_map(|(set, flag)| set.then_some(flag))
+            .collect::<Vec<_>>()
+            .join(",");
+        format!(
+            "{}\t{}\t{}\t{}\t{}",
+            self.output,
+            self.index,
+            self.number
+                .map_or_else(|| "-".to_owned(), |x| x.to_string()),
+            if flags.is_empty() { "-" } else { &flags },
+            self.name
+        )
+    }
+}
+
+/// Print `value` as a single line of JSON on stdout
+fn print_json(value: &impl Serialize) -> Result<(), QueryError> {
+    let mut stdout = std::io::stdout().lock();
+    serde_json::to_writer(&mut stdout, value)?;
+    Ok(writeln!(stdout)?)
+}
+
+/// Options of the `list` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ListArgs {
+    /// Only list the workspaces of this output
+    #[clap(long)]
+    output: Option<String>,
+
+    /// Print a JSON array instead of a line per workspace
+    #[clap(long)]
+    json: bool,
+}
+
+impl ListArgs {
+    /// Print each workspace with its output, position, number, and whether it is visible or focused
+    pub fn run(&self, conn: &mut Connection) -> Result<(), QueryError> {
+        let workspaces = get_workspaces(conn)?;
+        let entries = WorkspaceEntry::all(&workspaces)
+            .into_iter()
+            .filter(|x| self.output.as_ref().is_none_or(|output| x.output == output))
+            .collect::<Vec<_>>();
+        if self.json {
+            return print_json(&entries);
+        }
+        let mut stdout = std::io::stdout().lock();
+        for entry in entries {
+            writeln!(stdout, "{}", entry.line())?;
+        }
+        Ok(())
+    }
+}
+
+/// Focused output, workspace and container, as printed by `focused`
+#[derive(Serialize, Debug)]
+struct FocusEntry<'a> {
+    output: &'a str,
+    workspace: &'a str,
+    container: i64,
+}
+
+/// Options of the `focused` subcommand
+#[derive(clap::Args, Debug)]
+pub struct FocusedArgs {
+    /// Print a JSON object instead of a tab separated line
+    #[clap(long)]
+    json: bool,
+}
+
+impl FocusedArgs {
+    /// Print the focused `output`, `workspace` and `container`
+    ///
+    /// The line holds the output, container id and workspace, the workspace last as its name may contain tabs
+    pub fn run(&self, output: &str, workspace: &str, container: i64) -> Result<(), QueryError> {
+        let entry = FocusEntry {
+            output,
+            workspace,
+            container,
+        };
+        if self.json {
+            return print_json(&entry);
+        }
+        Ok(writeln!(
+            std::io::stdout(),
+            "{}\t{}\t{}",
+            entry.output,
+            entry.container,
+            entry.workspace
+        )?)
+    }
+}
+
+/// Workspace of an output, as printed by `outputs`
+#[derive(Serialize, Debug)]
+struct OutputWorkspace<'a> {
+    name: &'a str,
+    visible: bool,
+}
+
+/// Active output with its workspaces in order, as printed by `outputs`
+#[derive(Serialize, Debug)]
+struct OutputEntry<'a> {
+    name: &'a str,
+    workspaces: Vec<OutputWorkspace<'a>>,
+}
+
+/// Options of the `outputs` subcommand
+#[derive(clap::Args, Debug)]
+pub struct OutputsArgs {
+    /// Print a JSON array instead of indented lines
+    #[clap(long)]
+    json: bool,
+}
+
+impl OutputsArgs {
+    /// Print each active output, followed by its workspaces in order with the visible one marked by `*`
+    pub fn run(&self, conn: &mut Connection) -> Result<(), QueryError> {
+        log::debug!("Requesting outputs");
+        let outputs = conn.get_outputs()?;
+        let workspaces = get_workspaces(conn)?;
+        let entries = outputs
+            .iter()
+            .filter(|x| x.active)
+            .map(|output| OutputEntry {
+                name: &output.name,
+                workspaces: workspaces
+                    .iter()
+                    .filter(|x| x.output == output.name)
+                    .map(|x| OutputWorkspace {
+                        name: &x.name,
+                        visible: x.visible,
+                    })
+                    .collect(),
+            })
+            .collect::<Vec<_>>();
+        if self.json {
+            return print_json(&entries);
+        }
+        let mut stdout = std::io::stdout().lock();
+        for entry in entries {
+            writeln!(stdout, "{}", entry.name)?;
+            for workspace in entry.workspaces {
+                let marker = if workspace.visible { "*" } else { " " };
+                writeln!(stdout, "{marker} {}", workspace.name)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Options of the `where` subcommand
+#[derive(clap::Args, Debug)]
+pub struct WhereArgs {
+    /// Workspace whose position is printed
+    #[clap(short, long)]
+    workspace: String,
+}
+
+impl WhereArgs {
+    /// Print the output and position of the workspace, separated by a tab
+    ///
+    /// Fails if no workspace has the name
+    pub fn run(&self, conn: &mut Connection) -> Result<(), QueryError> {
+        let workspaces = get_workspaces(conn)?;
+        let entry = WorkspaceEntry::all(&workspaces)
+            .into_iter()
+            .find(|x| x.name == self.workspace)
+            .ok_or_else(|| QueryError::NoWorkspace(self.workspace.clone()))?;
+        Ok(writeln!(
+            std::io::stdout(),
+            "{}\t{}",
+            entry.output,
+            entry.index
+        )?)
+    }
+}
@@ -0,0 +1,246 @@
+//! Query the container tree with properties which `i3ipc` does not expose
+use std::{
+    io::{Read, Write},
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    process::Command,
+};
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{connection::Connection, retry::RetryPolicy};
+
+/// Magic string starting every IPC message
+const MAGIC: &[u8; 6] = b"i3-ipc";
+/// IPC message type of `GET_TREE`
+const GET_TREE: u32 = 4;
+/// IPC message type of `GET_VERSION`
+const GET_VERSION: u32 = 7;
+
+/// Errors for `get_tree` and `get_variant`
+#[derive(Debug, Error)]
+pub enum RawTreeError {
+    #[error("Could not find IPC socket: {0}")]
+    SocketPath(std::io::Error),
+    #[error("IPC socket communication failed: {0}")]
+    Communication(#[from] std::io::Error),
+    #[error("Malformed IPC reply")]
+    MalformedReply,
+    #[error("Could not parse tree: {0}")]
+    Parse(#[from] serde_json::Error),
+}
+
+/// Node of the container tree, holding the properties `i3ipc::reply::Node` lacks
+#[derive(Debug, Default, Deserialize)]
+pub struct RawNode {
+    pub id: i64,
+    pub name: Option<String>,
+    #[serde(rename = "type")]
+    pub nodetype: String,
+    /// Layout of the children, like `splith` or `tabbed`
+    #[serde(default)]
+    pub layout: String,
+    /// Share of the parent's space
+    pub percent: Option<f64>,
+    #[serde(default)]
+    pub marks: Vec<String>,
+    /// Whether the floating container is shown on all workspaces
+    #[serde(default)]
+    pub sticky: bool,
+    /// 0 if not fullscreen, 1 if fullscreen on its output, 2 if fullscreen across all outputs
+    #[serde(default)]
+    pub fullscreen_mode: Option<u8>,
+    /// Position and size, relative to the whole screen
+    #[serde(default)]
+    pub rect: Rect,
+    #[serde(default)]
+    pub focused: bool,
+    /// Ids of the children, from the most to the least recently focused
+    #[serde(default)]
+    pub focus: Vec<i64>,
+    #[serde(default)]
+    pub urgent: bool,
+    /// Wayland application id, only set by sway
+    pub app_id: Option<String>,
+    /// Process id of the window, only set by sway
+    pub pid: Option<u32>,
+    /// X11 window id
+    pub window: Option<u64>,
+    /// X11 window properties, not set for wayland windows
+    pub window_properties: Option<WindowProperties>,
+    #[serde(default)]
+    pub nodes: Vec<RawNode>,
+    #[serde(default)]
+    pub floating_nodes: Vec<RawNode>,
+}
+
+/// Position and size of a node
+#[derive(Debug, Default, Deserialize)]
+pub struct Rect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+/// X11 properties of a window
+#[derive(Debug, Default, Deserialize)]
+pub struct WindowProperties {
+    pub class: Option<String>,
+    pub instance: Option<String>,
+    pub title: Option<String>,
+    pub window_role: Option<String>,
+}
+
+impl RawNode {
+    /// Iterate over the tiling and floating children of this node
+    pub fn children(&self) -> impl Iterator<Item = &Self> {
+        self.nodes.iter().chain(self.floating_nodes.iter())
+    }
+
+    /// Get all nodes below this node, parents before their children
+    #[must_use]
+    pub fn descendants(&self) -> Vec<&Self> {
+        let mut nodes = Vec::new();
+        for child in self.children() {
+            nodes.push(child);
+            nodes.extend(child.descendants());
+        }
+        nodes
+    }
+
+    /// Find the node with the given id in the subtree of this node
+    #[must_use]
+    pub fn find(&self, id: i64) -> Option<&Self> {
+        if self.id == id {
+            return Some(self);
+        }
+        self.children().find_map(|x| x.find(id))
+    }
+
+    /// Get the X11 window class, or the wayland application id of this node
+    #[must_use]
+    pub fn class(&self) -> Option<&str> {
+        self.window_properties
+            .as_ref()
+            .and_then(|x| x.class.as_deref())
+            .or(self.app_id.as_deref())
+    }
+
+    /// Collect the ids of the windows below this node whose class or app id is `class`, ignoring case
+    pub fn windows_of_class(&self, class: &str, ids: &mut Vec<i64>) {
+        if self.class().is_some_and(|x| x.eq_ignore_ascii_case(class)) {
+            ids.push(self.id);
+        }
+        for child in self.children() {
+            child.windows_of_class(class, ids);
+        }
+    }
+
+    /// Get the nodes from this node down to the node with the given id, both included
+    #[must_use]
+    pub fn path_to(&self, id: i64) -> Option<Vec<&Self>> {
+        if self.id == id {
+            return Some(vec![self]);
+        }
+        let mut path = self.children().find_map(|x| x.path_to(id))?;
+        path.insert(0, self);
+        Some(path)
+    }
+
+    /// Check if the node with the given id is floating, or inside a floating container
+    #[must_use]
+    pub fn is_floating(&self, id: i64) -> bool {
+        self.path_to(id).is_some_and(|path| {
+            path.iter().any(|x| x.nodetype == "floating_con")
+                || path
+                    .iter()
+                    .any(|x| x.floating_nodes.iter().any(|floating| floating.id == id))
+        })
+    }
+
+    /// Find the node holding the X11 window `window` in the subtree of this node
+    #[must_use]
+    pub fn find_window(&self, window: u64) -> Option<&Self> {
+        if self.window == Some(window) {
+            return Some(self);
+        }
+        self.children().find_map(|x| x.find_window(window))
+    }
+}
+
+/// Find the IPC socket the same way `i3ipc` does
+fn socket_path() -> Result<PathBuf, RawTreeError> {
+    if let Some(path) = std::env::var_os("I3SOCK") {
+        return Ok(path.into());
+    }
+    let output = Command::new("i3")
+        .arg("--get-socketpath")
+        .output()
+        .map_err(RawTreeError::SocketPath)?;
+    Ok(String::from_utf8_lossy(&output.stdout).trim().into())
+}
+
+/// Get the container tree, on a new connection to the window manager of `conn`
+pub fn get_tree(conn: &Connection) -> Result<RawNode, RawTreeError> {
+    log::debug!("Requesting raw tree");
+    Ok(serde_json::from_slice(&request(
+        GET_TREE,
+        conn.retry_policy(),
+    )?)?)
+}
+
+/// Part of the `GET_VERSION` reply which `i3ipc` does not expose
+#[derive(Debug, Deserialize)]
+pub(crate) struct VersionReply {
+    /// `sway` for sway, not set by i3
+    pub variant: Option<String>,
+}
+
+/// Get the variant of the window manager, `sway` for sway and none for i3
+pub fn get_variant(retry: RetryPolicy) -> Result<Option<String>, RawTreeError> {
+    log::debug!("Requesting version");
+    let reply: VersionReply = serde_json::from_slice(&request(GET_VERSION, retry)?)?;
+    Ok(reply.variant)
+}
+
+/// Send a message without payload of type `message_type`, and get the payload of the reply
+///
+/// Retried following `retry` if the socket can not be found or the communication fails
+fn request(message_type: u32, retry: RetryPolicy) -> Result<Vec<u8>, RawTreeError> {
+    retry.retry(
+        "Raw IPC request",
+        |_| request_once(message_type),
+        |error| {
+            matches!(
+                error,
+                RawTreeError::SocketPath(_) | RawTreeError::Communication(_)
+            )
+        },
+    )
+}
+
+/// Send a message without payload of type `message_type` on a new connection
+fn request_once(message_type: u32) -> Result<Vec<u8>, RawTreeError> {
+    let mut stream = UnixStream::connect(socket_path()?)?;
+
+    let mut message = MAGIC.to_vec();
+    message.extend_from_slice(&0_u32.to_ne_bytes());
+    message.extend_from_slice(&message_type.to_ne_bytes());
+    stream.write_all(&message)?;
+
+    let mut magic = [0_u8; 6];
+    let mut length = [0_u8; 4];
+    let mut reply_type = [0_u8; 4];
+    stream.read_exact(&mut magic)?;
+    stream.read_exact(&mut length)?;
+    stream.read_exact(&mut reply_type)?;
+    if &magic != MAGIC || u32::from_ne_bytes(reply_type) != message_type {
+        return Err(RawTreeError::MalformedReply);
+    }
+
+    let mut payload = vec![0; u32::from_ne_bytes(length) as usize];
+    stream.read_exact(&mut payload)?;
+    Ok(payload)
+}
@@ -0,0 +1,97 @@
+//! Remove a workspace, keeping or killing its windows
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    numbered::{self, NumberedError, Numbering},
+    util::{get_workspaces, run_commands, CommandError},
+};
+
+/// Errors while removing a workspace
+#[derive(Debug, Error)]
+pub enum RemoveError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not remove workspace: {0}")]
+    Command(#[from] CommandError),
+    #[error("Could not find workspace \"{0}\"")]
+    NoWorkspace(String),
+    #[error("Could not compact workspace numbers: {0}")]
+    Numbered(#[from] NumberedError),
+}
+
+/// Options of the `remove` subcommand
+#[derive(clap::Args, Debug)]
+pub struct RemoveArgs {
+    /// Workspace to remove, defaults to the focused workspace
+    #[clap(short, long)]
+    workspace: Option<String>,
+
+    /// Workspace receiving the windows of the removed workspace
+    #[clap(long, required_unless_present = "kill")]
+    adopt: Option<String>,
+
+    /// Kill the windows of the removed workspace instead
+    #[clap(long, conflicts_with = "adopt")]
+    kill: bool,
+
+    /// Close the gaps in the workspace numbers of the output afterwards
+    #[clap(long)]
+    compact: bool,
+
+    /// Close the gaps on the output of the removed workspace, or across all outputs
+    #[clap(long, value_enum, requires = "compact", default_value_t = Numbering::PerOutput)]
+    numbering: Numbering,
+}
+
+impl RemoveArgs {
+    /// Empty the workspace, and focus another one if it is focused, so i3 removes it
+    pub fn run(self, conn: &mut Connection, focused_workspace: &str) -> Result<(), RemoveError> {
+        let name = self
+            .workspace
+            .unwrap_or_else(|| focused_workspace.to_owned());
+        let workspaces = get_workspaces(conn)?;
+        let removed = workspaces
+            .iter()
+            .find(|x| x.name == name)
+            .ok_or_else(|| RemoveError::NoWorkspace(name.clone()))?;
+        if let Some(adopt) = &self.adopt {
+            if !workspaces.iter().any(|x| &x.name == adopt) {
+                return Err(RemoveError::NoWorkspace(adopt.clone()));
+            }
+        }
+        let criteria = format!("[workspace=\"^{}$\"]", regex::escape(&name));
+        let mut commands = vec![self.adopt.as_ref().map_or_else(
+            || format!("{criteria} kill"),
+            |adopt| format!("{criteria} move container to workspace \"{adopt}\""),
+        )];
+        if name == focused_workspace {
+            // Focus the adopting workspace, or a neighbor on the same output
+            let output_workspaces = workspaces
+                .iter()
+                .filter(|x| x.output == removed.output)
+                .collect::<Vec<_>>();
+            let position = output_workspaces
+                .iter()
+                .position(|x| x.name == name)
+                .unwrap_or_default();
+            let neighbor = output_workspaces
+                .get(position + 1)
+                .or_else(|| {
+                    position
+                        .checked_sub(1)
+                        .and_then(|x| output_workspaces.get(x))
+                })
+                .map(|x| &x.name);
+            if let Some(target) = self.adopt.as_ref().or(neighbor) {
+                commands.push(format!("workspace \"{target}\""));
+            }
+        }
+        run_commands(conn, &commands)?;
+        if self.compact {
+            let output = (self.numbering == Numbering::PerOutput).then_some(&removed.output);
+            numbered::compact(conn, self.numbering, output.map(String::as_str))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,583 @@
+//! Reorder the existing workspaces of an output
+use i3ipc::reply::Workspace;
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    insert_workspace_swap,
+    insertion::InsertMode,
+    pivot::{PivotError, PivotSelection},
+    raw_tree::{self, RawTreeError},
+    util::{get_workspaces, run_commands, CommandError, Pivot},
+};
+
+/// Errors while reordering workspaces
+#[derive(Debug, Error)]
+pub enum ReorderError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not reorder workspaces: {0}")]
+    Command(#[from] CommandError),
+    #[error("Could not find workspace \"{0}\"")]
+    NoWorkspace(String),
+    #[error("Workspaces \"{0}\" and \"{1}\" are on different outputs")]
+    DifferentOutputs(String, String),
+    #[error("Pivot error: {0}")]
+    Pivot(#[from] PivotError),
+    #[error("Could not get tree: {0}")]
+    RawTree(#[from] RawTreeError),
+}
+
+/// Names of the workspaces to move to the end of their output, in this order, to order `workspaces` like `order`
+///
+/// Workspaces can only be moved to the end of their output, so every workspace from the first one out of place on is moved
+fn out_of_place<'a>(workspaces: &[&Workspace], order: &[&'a Workspace]) -> Vec<&'a str> {
+    let in_place = workspaces
+        .iter()
+        .zip(order)
+        .take_while(|(current, wanted)| current.name == wanted.name)
+        .count();
+    order
+        .iter()
+        .skip(in_place)
+        .map(|x| x.name.as_str())
+        .collect()
+}
+
+/// Commands ordering the workspaces `workspaces` of an output like `order`
+///
+/// Renaming a workspace to its own name moves it to the end of its output in i3
+fn reorder_commands(workspaces: &[&Workspace], order: &[&Workspace]) -> Vec<String> {
+    out_of_place(workspaces, order)
+        .into_iter()
+        .map(|x| format!("rename workspace \"{x}\" to \"{x}\""))
+        .collect()
+}
+
+/// Order the workspaces `workspaces` of an output like `order`, using the method for `mode`
+///
+/// Sway keeps the position of a workspace renamed to its own name,
+/// so there each moved one is emptied into a dummy workspace taking its name, like by the sway insertion
+fn reorder(
+    conn: &mut Connection,
+    mode: &InsertMode,
+    workspaces: &[&Workspace],
+    order: &[&Workspace],
+) -> Result<(), ReorderError> {
+    let commands = match mode {
+        InsertMode::I3 => reorder_commands(workspaces, order),
+        InsertMode::Sway => {
+            let moved = out_of_place(workspaces, order);
+            match workspaces.first() {
+                Some(first) if !moved.is_empty() => insert_workspace_swap::reorder_commands(
+                    &raw_tree::get_tree(conn)?,
+                    &first.output,
+                    &moved,
+                ),
+                _ => Vec::new(),
+            }
+        }
+    };
+    Ok(run_commands(conn, &commands)?)
+}
+
+/// Find the workspace named `name`
+fn find_workspace<'a>(
+    workspaces: &'a [Workspace],
+    name: &str,
+) -> Result<&'a Workspace, ReorderError> {
+    workspaces
+        .iter()
+        .find(|x| x.name == name)
+        .ok_or_else(|| ReorderError::NoWorkspace(name.to_owned()))
+}
+
+/// Replace the workspace named `name` in `workspaces` by `replacement`
+fn replaced<'a>(
+    workspaces: &[&'a Workspace],
+    name: &str,
+    replacement: &'a Workspace,
+) -> Vec<&'a Workspace> {
+    workspaces
+        .iter()
+        .map(|x| if x.name == name { replacement } else { *x })
+        .collect()
+}
+
+/// Get the workspaces on `output`, in their current order
+fn output_workspaces<'a>(workspaces: &'a [Workspace], output: &str) -> Vec<&'a Workspace> {
+    workspaces.iter().filter(|x| x.output == output).collect()
+}
+
+/// Direction in the order of workspaces of an output
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum Direction {
+    /// Towards the first workspace
+    Left,
+    /// Towards the last workspace
+    Right,
+}
+
+/// Keys to sort workspaces by
+#[derive(clap::ValueEnum, Clone, Copy, Debug)]
+enum SortKey {
+    /// Workspace name
+    Name,
+    /// Workspace number, unnumbered workspaces are sorted by name after the numbered ones
+    Number,
+}
+
+/// Options of the `sort` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SortArgs {
+    /// Output whose workspaces are sorted, defaults to the focused output
+    #[clap(long)]
+    output: Option<String>,
+
+    /// What to sort the workspaces by
+    #[clap(long, value_enum, default_value_t = SortKey::Name)]
+    by: SortKey,
+
+    /// Sort in descending order
+    #[clap(long)]
+    descending: bool,
+}
+
+impl SortArgs {
+    /// Sort the workspaces of the output, using the method for `mode`
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_output: &str,
+    ) -> Result<(), ReorderError> {
+        let workspaces = get_workspaces(conn)?;
+        let current = output_workspaces(
+            &workspaces,
+            self.output.as_deref().unwrap_or(focused_output),
+        );
+        let mut order = current.clone();
+        match self.by {
+            SortKey::Name => order.sort_by(|a, b| a.name.cmp(&b.name)),
+            SortKey::Number => order.sort_by_key(|x| (x.num < 0, x.num, x.name.clone())),
+        }
+        if self.descending {
+            order.reverse();
+        }
+        reorder(conn, mode, &current, &order)
+    }
+}
+
+/// Options of the `swap` subcommand
+#[derive(clap::Args, Debug)]
+pub struct SwapArgs {
+    /// First workspace
+    #[clap(long)]
+    a: String,
+
+    /// Second workspace
+    #[clap(long)]
+    b: String,
+
+    /// Also exchange the outputs of the workspaces, if they are on different outputs
+    #[clap(long)]
+    with_outputs: bool,
+}
+
+impl SwapArgs {
+    /// Exchange the positions of the two workspaces, using the method for `mode`
+    ///
+    /// Moving workspaces between outputs requires focusing them, so focus returns to `focused_workspace` afterwards
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_workspace: &str,
+    ) -> Result<(), ReorderError> {
+        let workspaces = get_workspaces(conn)?;
+        let a = find_workspace(&workspaces, &self.a)?;
+        let b = find_workspace(&workspaces, &self.b)?;
+        let a_output = output_workspaces(&workspaces, &a.output);
+        if a.output == b.output {
+            let order = a_output
+                .iter()
+                .map(|x| match x.name.as_str() {
+                    name if name == a.name => b,
+                    name if name == b.name => a,
+                    _ => *x,
+                })
+                .collect::<Vec<_>>();
+            return reorder(conn, mode, &a_output, &order);
+        }
+        if !self.with_outputs {
+            return Err(ReorderError::DifferentOutputs(self.a, self.b));
+        }
+        let b_output = output_workspaces(&workspaces, &b.output);
+        let mut commands = vec![
+            format!("workspace \"{}\"", a.name),
+            format!("move workspace to output \"{}\"", b.output),
+            format!("workspace \"{}\"", b.name),
+            format!("move workspace to output \"{}\"", a.output),
+            format!("workspace \"{focused_workspace}\""),
+        ];
+        if let InsertMode::Sway = mode {
+            // The dummy workspaces need the tree after the move, so the workspaces are reordered in a second step
+            run_commands(conn, &commands)?;
+            let moved = get_workspaces(conn)?;
+            reorder(
+                conn,
+                mode,
+                &output_workspaces(&moved, &a.output),
+                &replaced(&a_output, &a.name, b),
+            )?;
+            return reorder(
+                conn,
+                mode,
+                &output_workspaces(&moved, &b.output),
+                &replaced(&b_output, &b.name, a),
+            );
+        }
+        // i3 renames every workspace from the swapped position on, which needs no tree and fits the same message
+        commands.extend(reorder_commands(
+            &a_output,
+            &replaced(&a_output, &a.name, b),
+        ));
+        commands.extend(reorder_commands(
+            &b_output,
+            &replaced(&b_output, &b.name, a),
+        ));
+        Ok(run_commands(conn, &commands)?)
+    }
+}
+
+/// Options of the `move` subcommand
+#[derive(clap::Args, Debug)]
+pub struct MoveArgs {
+    /// Workspace to move, defaults to the focused workspace
+    #[clap(short, long)]
+    workspace: Option<String>,
+
+    #[clap(flatten)]
+    pivot: PivotSelection,
+
+    /// Move before the pivot instead of after it.
+    #[clap(short, long)]
+    before: bool,
+
+    /// Output the workspace is moved to, the pivot has to be on this output.
+    ///
+    /// Tells apart equally named pivots on different outputs, like `--on-output`
+    #[clap(long)]
+    to_output: Option<String>,
+}
+
+impl MoveArgs {
+    /// Move the workspace next to the pivot, onto the output of the pivot, using the method for `mode`
+    pub fn run(
+        mut self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_workspace: &str,
+    ) -> Result<(), ReorderError> {
+        let name = self
+            .workspace
+            .unwrap_or_else(|| focused_workspace.to_owned());
+        if let Some(output) = self.to_output {
+            self.pivot.restrict_output(output);
+        }
+        let pivot = self.pivot.resolve(conn, focused_workspace.to_owned())?;
+        move_workspace(conn, mode, &name, &pivot, self.before, focused_workspace)
+    }
+}
+
+/// Move the workspace `name` next to `pivot`, onto the output of the pivot, using the method for `mode`
+///
+/// Moving workspaces between outputs requires focusing them, so focus returns to `focused_workspace` afterwards
+pub fn move_workspace(
+    conn: &mut Connection,
+    mode: &InsertMode,
+    name: &str,
+    pivot: &Pivot,
+    before: bool,
+    focused_workspace: &str,
+) -> Result<(), ReorderError> {
+    let workspaces = get_workspaces(conn)?;
+    let moved = find_workspace(&workspaces, name)?;
+    let output = pivot
+        .output
+        .as_ref()
+        .ok_or_else(|| ReorderError::NoWorkspace(pivot.name.clone()))?;
+    if moved.name == pivot.name {
+        return Ok(());
+    }
+    let current = output_workspaces(&workspaces, output);
+    let mut order = current
+        .iter()
+        .copied()
+        .filter(|x| x.name != moved.name)
+        .collect::<Vec<_>>();
+    let pivot_index = order
+        .iter()
+        .position(|x| x.name == pivot.name)
+        .ok_or_else(|| ReorderError::NoWorkspace(pivot.name.clone()))?;
+    order.insert(if before { pivot_index } else { pivot_index + 1 }, moved);
+    let mut commands = Vec::new();
+    if &moved.output != output {
+        commands.push(format!("workspace \"{}\"", moved.name));
+        commands.push(format!("move workspace to output \"{output}\""));
+        commands.push(format!("workspace \"{focused_workspace}\""));
+    }
+    if let InsertMode::Sway = mode {
+        if commands.is_empty() {
+            return reorder(conn, mode, &current, &order);
+        }
+        // The dummy workspaces need the tree after the move, so the workspaces are reordered in a second step
+        run_commands(conn, &commands)?;
+        let workspaces = get_workspaces(conn)?;
+        return reorder(conn, mode, &output_workspaces(&workspaces, output), &order);
+    }
+    commands.extend(reorder_commands(&current, &order));
+    Ok(run_commands(conn, &commands)?)
+}
+
+/// Options of the `rotate` subcommand
+#[derive(clap::Args, Debug)]
+pub struct RotateArgs {
+    /// Output whose workspaces are rotated, defaults to the focused output
+    #[clap(long)]
+    output: Option<String>,
+
+    /// Direction the workspaces are shifted in, workspaces shifted over the edge wrap around
+    #[clap(long, value_enum)]
+    direction: Direction,
+
+    /// Number of positions the workspaces are shifted by
+    #[clap(long, default_value_t = 1)]
+    count: usize,
+}
+
+impl RotateArgs {
+    /// Cyclically shift the workspaces of the output, using the method for `mode`
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_output: &str,
+    ) -> Result<(), ReorderError> {
+        let workspaces = get_workspaces(conn)?;
+        let current = output_workspaces(
+            &workspaces,
+            self.output.as_deref().unwrap_or(focused_output),
+        );
+        let mut order = current.clone();
+        if !order.is_empty() {
+            let count = self.count % order.len();
+            match self.direction {
+                Direction::Left => order.rotate_left(count),
+                Direction::Right => order.rotate_right(count),
+            }
+        }
+        reorder(conn, mode, &current, &order)
+    }
+}
+
+/// Options of the `reverse` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ReverseArgs {
+    /// Output whose workspaces are reversed, defaults to the focused output
+    #[clap(long)]
+    output: Option<String>,
+}
+
+impl ReverseArgs {
+    /// Flip the order of the workspaces of the output, using the method for `mode`
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_output: &str,
+    ) -> Result<(), ReorderError> {
+        let workspaces = get_workspaces(conn)?;
+        let current = output_workspaces(
+            &workspaces,
+            self.output.as_deref().unwrap_or(focused_output),
+        );
+        let order = current.iter().rev().copied().collect::<Vec<_>>();
+        reorder(conn, mode, &current, &order)
+    }
+}
+
+/// Options of the `bubble` subcommand
+#[derive(clap::Args, Debug)]
+pub struct BubbleArgs {
+    /// Direction the focused workspace moves in
+    #[clap(long, value_enum)]
+    direction: Direction,
+}
+
+impl BubbleArgs {
+    /// Swap the focused workspace with its neighbor on the same output, using the method for `mode`
+    ///
+    /// Does nothing if the focused workspace is already at the edge of its output
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_workspace: &str,
+    ) -> Result<(), ReorderError> {
+        let workspaces = get_workspaces(conn)?;
+        let focused = find_workspace(&workspaces, focused_workspace)?;
+        let current = output_workspaces(&workspaces, &focused.output);
+        let position = current
+            .iter()
+            .position(|x| x.name == focused.name)
+            .unwrap_or_default();
+        let neighbor = match self.direction {
+            Direction::Left => position.checked_sub(1),
+            Direction::Right => Some(position + 1).filter(|x| *x < current.len()),
+        };
+        let Some(neighbor) = neighbor else {
+            return Ok(());
+        };
+        let mut order = current.clone();
+        order.swap(position, neighbor);
+        reorder(conn, mode, &current, &order)
+    }
+}
+
+/// Options of the `archive` subcommand
+#[derive(clap::Args, Debug)]
+pub struct ArchiveArgs {
+    /// Workspace to archive, defaults to the focused workspace
+    #[clap(short, long)]
+    workspace: Option<String>,
+
+    /// Output collecting archived workspaces, defaults to the output of the workspace
+    #[clap(long)]
+    to_output: Option<String>,
+}
+
+impl ArchiveArgs {
+    /// Move the workspace to the last position of its output, or of the archive output, using the method for `mode`
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        mode: &InsertMode,
+        focused_workspace: &str,
+    ) -> Result<(), ReorderError> {
+        let name = self
+            .workspace
+            .unwrap_or_else(|| focused_workspace.to_owned());
+        let workspaces = get_workspaces(conn)?;
+        let archived = find_workspace(&workspaces, &name)?;
+        let mut commands = Vec::new();
+        let to_output = self.to_output.filter(|x| x != &archived.output);
+        if let Some(output) = &to_output {
+            commands.push(format!("workspace \"{name}\""));
+            commands.push(format!("move workspace to output \"{output}\""));
+            commands.push(format!("workspace \"{focused_workspace}\""));
+        }
+        let output = to_output.unwrap_or_else(|| archived.output.clone());
+        if let InsertMode::Sway = mode {
+            // The dummy workspace needs the tree after the move
+            run_commands(conn, &commands)?;
+            let workspaces = get_workspaces(conn)?;
+            let current = output_workspaces(&workspaces, &output);
+            let (mut order, archived): (Vec<_>, Vec<_>) =
+                current.iter().partition(|x| x.name != name);
+            order.extend(archived);
+            return reorder(conn, mode, &current, &order);
+        }
+        // Renaming moves the workspace to the end of its output
+        commands.push(format!("rename workspace \"{name}\" to \"{name}\""));
+        Ok(run_commands(conn, &commands)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use i3ipc::reply::Workspace;
+
+    use super::{find_workspace, out_of_place, reorder_commands, replaced, ReorderError};
+    use crate::{
+        backend::{BackendError, WmBackend},
+        insert_workspace_swap,
+        mock::MockBackend,
+    };
+
+    fn backend() -> MockBackend {
+        MockBackend::with_outputs(&[("A", &["a", "b", "c", "d"]), ("B", &["e", "f"])])
+    }
+
+    /// Workspaces named `names` among `workspaces`, in this order
+    fn ordered<'a>(workspaces: &'a [Workspace], names: &[&str]) -> Vec<&'a Workspace> {
+        names
+            .iter()
+            .filter_map(|name| workspaces.iter().find(|x| x.name == *name))
+            .collect()
+    }
+
+    #[test]
+    fn moves_from_the_first_misplaced_workspace_on() -> Result<(), BackendError> {
+        let workspaces = backend().get_workspaces()?;
+        let current = ordered(&workspaces, &["a", "b", "c", "d"]);
+        let order = ordered(&workspaces, &["a", "c", "b", "d"]);
+        assert_eq!(out_of_place(&current, &order), ["c", "b", "d"]);
+        assert!(out_of_place(&current, &current).is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn reorders_by_renaming() -> Result<(), BackendError> {
+        let mut backend = backend();
+        let workspaces = backend.get_workspaces()?;
+        let current = ordered(&workspaces, &["a", "b", "c", "d"]);
+        let order = ordered(&workspaces, &["d", "b", "a", "c"]);
+        backend.run_commands(&reorder_commands(&current, &order))?;
+        assert_eq!(backend.workspace_names("A"), ["d", "b", "a", "c"]);
+        assert_eq!(backend.workspace_names("B"), ["e", "f"]);
+        Ok(())
+    }
+
+    #[test]
+    fn reorders_through_dummy_workspaces() -> Result<(), BackendError> {
+        let mut backend = backend();
+        let focused = backend.focused().map(ToOwned::to_owned);
+        let workspaces = backend.get_workspaces()?;
+        let current = ordered(&workspaces, &["a", "b", "c", "d"]);
+        let order = ordered(&workspaces, &["a", "d", "c", "b"]);
+        let moved = out_of_place(&current, &order);
+        let commands = insert_workspace_swap::reorder_commands(&backend.get_tree()?, "A", &moved);
+        backend.run_commands(&commands)?;
+        assert_eq!(backend.workspace_names("A"), ["a", "d", "c", "b"]);
+        assert_eq!(backend.workspace_names("B"), ["e", "f"]);
+        assert_eq!(backend.focused().map(ToOwned::to_owned), focused);
+        Ok(())
+    }
+
+    #[test]
+    fn replaces_swapped_workspace() -> Result<(), BackendError> {
+        let workspaces = backend().get_workspaces()?;
+        let current = ordered(&workspaces, &["a", "b", "c", "d"]);
+        let replacement = ordered(&workspaces, &["e"]);
+        let [e] = replacement.as_slice() else {
+            panic!("expected one workspace, got {}", replacement.len());
+        };
+        let order = replaced(&current, "b", e);
+        assert_eq!(
+            order.iter().map(|x| x.name.as_str()).collect::<Vec<_>>(),
+            ["a", "e", "c", "d"]
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn missing_workspace() -> Result<(), BackendError> {
+        let workspaces = backend().get_workspaces()?;
+        assert!(matches!(
+            find_workspace(&workspaces, "x"),
+            Err(ReorderError::NoWorkspace(name)) if name == "x"
+        ));
+        Ok(())
+    }
+}
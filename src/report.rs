@@ -0,0 +1,164 @@
+//! Output of the result of an insertion, for scripts
+use std::io::Write;
+
+use serde::Serialize;
+use thiserror::Error;
+
+use crate::{connection::Connection, plan::Plan, util::get_workspaces};
+
+/// Errors for `ReportArgs::print`
+#[derive(Debug, Error)]
+pub enum ReportError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not write JSON: {0}")]
+    Json(#[from] serde_json::Error),
+}
+
+/// Options to print the result on stdout
+#[derive(clap::Args, Debug)]
+pub struct ReportArgs {
+    /// Print the names of the new workspaces on stdout.
+    ///
+    /// The names are the only thing printed on stdout, each followed by a newline, so scripts can capture them
+    #[clap(long, env = "I3IW_PRINT_NAME")]
+    print_name: bool,
+
+    /// Print the result as a JSON object on stdout.
+    ///
+    /// Lists the new workspaces with their output and position, the moved container ids and the sent commands,
+    /// which are not printed separately with `--dry-run`
+    #[clap(long, env = "I3IW_JSON", conflicts_with = "print_name")]
+    pub json: bool,
+
+    /// Print a line formatted by this template for each new workspace.
+    ///
+    /// Placeholders: `{name}` name of the workspace, `{output}` name of its output,
+    /// `{index}` position on its output counted from 1. With `--dry-run` the output and position are the planned ones
+    #[clap(long, env = "I3IW_FORMAT", conflicts_with_all = ["print_name", "json"])]
+    format: Option<String>,
+}
+
+impl ReportArgs {
+    /// Whether the report lists the sent commands, which the connection then has to record
+    #[must_use]
+    pub fn needs_commands(&self) -> bool {
+        self.json
+    }
+
+    /// Print the new workspaces `names`, after `containers` were moved to the first of them
+    ///
+    /// Workspaces which do not exist, as with `--dry-run`, are reported where `plan` expects them
+    pub fn print(
+        &self,
+        conn: &mut Connection,
+        names: &[String],
+        containers: &[i64],
+        plan: Option<&Plan>,
+    ) -> Result<(), ReportError> {
+        if self.json {
+            InsertionReport::new(conn, names, containers, plan)?.print()?;
+        } else if let Some(format) = &self.format {
+            let report = InsertionReport::new(conn, names, containers, plan)?;
+            // Deliberate output contract of `--format`, errors go to stderr
+            #[allow(clippy::print_stdout)]
+            for workspace in &report.workspaces {
+                println!("{}", workspace.format(format));
+            }
+        } else if self.print_name {
+            // Deliberate output contract of `--print-name`, errors go to stderr
+            #[allow(clippy::print_stdout)]
+            for name in names {
+                println!("{name}");
+            }
+        }
+        Ok(())
+    }
+}
+
+/// New workspace and its position after the insertion
+#[derive(Serialize, Debug)]
+struct WorkspaceReport {
+    name: String,
+    /// Output containing the workspace, the planned one with `--dry-run`
+    output: Option<String>,
+    /// Position of the workspace on its output, counted from 1 like `{index}` of `--name-template`
+    index: Option<usize>,
+}
+
+impl WorkspaceReport {
+    /// Expand the placeholders of a `--format` template
+    fn format(&self, template: &str) -> String {
+        template
+            .replace("{name}", &self.name)
+            .replace("{output}", self.output.as_deref().unwrap_or_default())
+            .replace(
+                "{index}",
+                &self.index.map(|x| x.to_string()).unwrap_or_default(),
+            )
+    }
+}
+
+/// Result of an insertion
+#[derive(Serialize, Debug)]
+struct InsertionReport {
+    /// New workspaces, in the order they were inserted
+    workspaces: Vec<WorkspaceReport>,
+    /// Containers moved to the first new workspace
+    containers: Vec<i64>,
+    /// Command messages sent to the window manager, or only planned with `--dry-run`
+    commands: Vec<String>,
+}
+
+impl InsertionReport {
+    /// Look up the positions of the workspaces `names` after `containers` were moved to them
+    ///
+    /// Missing workspaces are looked up in `plan`
+    fn new(
+        conn: &mut Connection,
+        names: &[String],
+        containers: &[i64],
+        plan: Option<&Plan>,
+    ) -> Result<Self, i3ipc::MessageError> {
+        let workspaces = get_workspaces(conn)?;
+        let workspaces = names
+            .iter()
+            .map(|name| {
+                let output = workspaces
+                    .iter()
+                    .find(|x| &x.name == name)
+                    .map(|x| x.output.clone());
+                let Some(output) = output else {
+                    let planned = plan.and_then(|plan| Some((plan, plan.index(name)?)));
+                    return WorkspaceReport {
+                        name: name.clone(),
+                        output: planned.map(|(plan, _)| plan.output.clone()),
+                        index: planned.map(|(_, index)| index),
+                    };
+                };
+                let index = workspaces
+                    .iter()
+                    .filter(|x| x.output == output)
+                    .position(|x| &x.name == name)
+                    .map(|x| x + 1);
+                WorkspaceReport {
+                    name: name.clone(),
+                    output: Some(output),
+                    index,
+                }
+            })
+            .collect();
+        Ok(Self {
+            workspaces,
+            containers: containers.to_vec(),
+            commands: conn.recorded_commands().to_vec(),
+        })
+    }
+
+    /// Print the report as a single line of JSON on stdout
+    fn print(&self) -> Result<(), serde_json::Error> {
+        let mut stdout = std::io::stdout().lock();
+        serde_json::to_writer(&mut stdout, self)?;
+        writeln!(stdout).map_err(serde_json::Error::io)
+    }
+}
@@ -0,0 +1,302 @@
+//! Insertion requests for library users, built step by step
+use thiserror::Error;
+
+use crate::{
+    backend::{BackendError, WmBackend},
+    insertion::{plan_with, FollowSelection, InsertMode, InsertionError},
+    plan::Plan,
+    util::{ContainerChanges, InsertionDestination, Pivot},
+};
+
+/// Errors of building and running an `InsertRequest`
+#[derive(Debug, Error)]
+pub enum RequestError {
+    #[error("No pivot workspace given")]
+    MissingPivot,
+    #[error("No name for the new workspace given")]
+    MissingName,
+    #[error("{0}")]
+    Insertion(#[from] InsertionError),
+    #[error("{0}")]
+    Backend(#[from] BackendError),
+    #[error("i3 IPC command error: \"{0}\"")]
+    Command(String),
+}
+
+/// Workspace focused after the insertion
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Focus {
+    /// Leave the focus as the insertion method does
+    #[default]
+    Unchanged,
+    /// Focus the workspace focused before the insertion, like `--no-follow`
+    Origin,
+    /// Focus the first new workspace and the first moved container in it, like `--follow`
+    New,
+}
+
+/// Insertion of new workspaces next to a pivot workspace, created by `InsertRequest::builder`
+#[derive(Clone, Debug)]
+pub struct InsertRequest {
+    destination: InsertionDestination,
+    names: Vec<String>,
+    containers: Vec<i64>,
+    mark: Option<String>,
+    focus: Focus,
+    mode: Option<InsertMode>,
+}
+
+impl InsertRequest {
+    /// Start building a request
+    #[must_use]
+    pub fn builder() -> InsertRequestBuilder {
+        InsertRequestBuilder::default()
+    }
+
+    /// Plan the insertion with the commands establishing the focus, without running it
+    ///
+    /// Without a mode set, it is detected through `backend` by the version reply of the window manager
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pivot does not exist, or if the window manager can not be reached
+    pub fn plan(&self, backend: &mut impl WmBackend) -> Result<Plan, RequestError> {
+        let origin = backend
+            .get_workspaces()?
+            .into_iter()
+            .find(|x| x.focused)
+            .map(|x| x.name)
+            .unwrap_or_default();
+        let mode = match &self.mode {
+            Some(mode) => mode.clone(),
+            None => backend.get_mode()?,
+        };
+        let mut plan = plan_with(
+            backend,
+            &mode,
+            &self.destination,
+            &self.names,
+            &self.containers,
+            ContainerChanges {
+                mark: self.mark.as_deref(),
+                unstick: &[],
+            },
+            &[],
+        )?;
+        if let Some(name) = self.names.first() {
+            let mut follow = FollowSelection::default();
+            follow.default_to(match self.focus {
+                Focus::Unchanged => None,
+                Focus::Origin => Some(false),
+                Focus::New => Some(true),
+            });
+            plan.commands
+                .extend(follow.commands(&origin, &[], name, &self.containers));
+        }
+        Ok(plan)
+    }
+
+    /// Run the insertion, returning the plan which was run
+    ///
+    /// # Errors
+    ///
+    /// Fails if the pivot does not exist, if the window manager can not be reached,
+    /// or if it rejects one of the commands
+    pub fn run(&self, backend: &mut impl WmBackend) -> Result<Plan, RequestError> {
+        let plan = self.plan(backend)?;
+        if let Some(failed) = plan.run(backend)?.into_iter().find(|x| !x.success) {
+            return Err(RequestError::Command(
+                failed
+                    .error
+                    .unwrap_or_else(|| "No error message, but errored".to_owned()),
+            ));
+        }
+        Ok(plan)
+    }
+}
+
+/// Builder of an `InsertRequest`, needing at least a pivot and a name
+#[derive(Clone, Debug, Default)]
+pub struct InsertRequestBuilder {
+    pivot: Option<Pivot>,
+    before: bool,
+    names: Vec<String>,
+    containers: Vec<i64>,
+    mark: Option<String>,
+    focus: Focus,
+    mode: Option<InsertMode>,
+}
+
+impl InsertRequestBuilder {
+    /// Insert next to the workspace named `name`
+    #[must_use]
+    pub fn pivot(mut self, name: impl Into<String>) -> Self {
+        let output = self.pivot.and_then(|x| x.output);
+        self.pivot = Some(Pivot {
+            name: name.into(),
+            output,
+        });
+        self
+    }
+
+    /// Only look for the pivot on the output `output`
+    #[must_use]
+    pub fn output(mut self, output: impl Into<String>) -> Self {
+        let name = self.pivot.map(|x| x.name).unwrap_or_default();
+        self.pivot = Some(Pivot {
+            name,
+            output: Some(output.into()),
+        });
+        self
+    }
+
+    /// Insert before the pivot, instead of after it
+    #[must_use]
+    pub const fn before(mut self) -> Self {
+        self.before = true;
+        self
+    }
+
+    /// Insert a workspace named `name`, after the ones named before
+    #[must_use]
+    pub fn name(mut self, name: impl Into<String>) -> Self {
+        self.names.push(name.into());
+        self
+    }
+
+    /// Move the container with the id `id` to the first new workspace
+    #[must_use]
+    pub fn container(mut self, id: i64) -> Self {
+        self.containers.push(id);
+        self
+    }
+
+    /// Mark the first moved container with `mark`
+    #[must_use]
+    pub fn mark(mut self, mark: impl Into<String>) -> Self {
+        self.mark = Some(mark.into());
+        self
+    }
+
+    /// Choose the workspace focused after the insertion
+    #[must_use]
+    pub const fn focus(mut self, focus: Focus) -> Self {
+        self.focus = focus;
+        self
+    }
+
+    /// Use the insertion method for `mode`, instead of detecting the window manager
+    #[must_use]
+    pub const fn mode(mut self, mode: InsertMode) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Finish the request
+    ///
+    /// # Errors
+    ///
+    /// Fails if no pivot or no name is given
+    pub fn build(self) -> Result<InsertRequest, RequestError> {
+        let pivot = self
+            .pivot
+            .filter(|x| !x.name.is_empty())
+            .ok_or(RequestError::MissingPivot)?;
+        if self.names.is_empty() {
+            return Err(RequestError::MissingName);
+        }
+        Ok(InsertRequest {
+            destination: InsertionDestination::new(pivot, self.before),
+            names: self.names,
+            containers: self.containers,
+            mark: self.mark,
+            focus: self.focus,
+            mode: self.mode,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Focus, InsertRequest, RequestError};
+    use crate::{insertion::InsertMode, mock::MockBackend};
+
+    fn backend() -> MockBackend {
+        MockBackend::with_outputs(&[("A", &["a", "b", "c"])])
+    }
+
+    #[test]
+    fn plans_before_pivot() -> Result<(), RequestError> {
+        let mut backend = backend();
+        let request = InsertRequest::builder()
+            .pivot("b")
+            .before()
+            .name("new")
+            .mode(InsertMode::I3)
+            .build()?;
+        let plan = request.plan(&mut backend)?;
+        assert_eq!(plan.output, "A");
+        assert_eq!(plan.workspaces, ["a", "new", "b", "c"]);
+        assert!(backend.commands().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn detects_mode_through_backend() -> Result<(), RequestError> {
+        let mut backend = backend();
+        let request = InsertRequest::builder().pivot("c").name("new").build()?;
+        let plan = request.run(&mut backend)?;
+        assert_eq!(backend.workspace_names("A"), ["a", "b", "c", "new"]);
+        assert_eq!(plan.workspaces, ["a", "b", "c", "new"]);
+        Ok(())
+    }
+
+    #[test]
+    fn runs_with_focus() -> Result<(), RequestError> {
+        let mut backend = backend();
+        backend.add_container("a", 9);
+        let plan = InsertRequest::builder()
+            .pivot("a")
+            .name("new")
+            .container(1)
+            .focus(Focus::New)
+            .mode(InsertMode::Sway)
+            .build()?
+            .run(&mut backend)?;
+        assert_eq!(backend.workspace_names("A"), plan.workspaces);
+        assert_eq!(backend.workspace_names("A"), ["a", "new", "b", "c"]);
+        assert_eq!(backend.focused(), Some("new"));
+        Ok(())
+    }
+
+    #[test]
+    fn missing_pivot() {
+        assert!(matches!(
+            InsertRequest::builder().name("new").build(),
+            Err(RequestError::MissingPivot)
+        ));
+        assert!(matches!(
+            InsertRequest::builder().output("A").name("new").build(),
+            Err(RequestError::MissingPivot)
+        ));
+    }
+
+    #[test]
+    fn missing_name() {
+        assert!(matches!(
+            InsertRequest::builder().pivot("a").build(),
+            Err(RequestError::MissingName)
+        ));
+    }
+
+    #[test]
+    fn missing_pivot_workspace() {
+        let result = InsertRequest::builder()
+            .pivot("f")
+            .name("new")
+            .mode(InsertMode::I3)
+            .build()
+            .and_then(|x| x.run(&mut backend()));
+        assert!(matches!(result, Err(RequestError::Insertion(_))));
+    }
+}
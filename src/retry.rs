@@ -0,0 +1,144 @@
+//! Retrying IPC requests failing while the window manager starts or restarts
+use std::{fmt::Display, time::Duration};
+
+/// How often to retry requests failing while the window manager starts or restarts
+///
+/// The default does not retry
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// Number of retries after a failed request
+    retries: u32,
+    /// Time to wait before the first retry, doubled for each further one
+    delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self::new(0, Duration::from_millis(100))
+    }
+}
+
+impl RetryPolicy {
+    /// Retry up to `retries` times, waiting `delay` before the first retry and doubling it for each further one
+    #[must_use]
+    pub const fn new(retries: u32, delay: Duration) -> Self {
+        Self { retries, delay }
+    }
+
+    /// Run `request` until it succeeds, fails with an error not `transient`, or the retries are used up
+    ///
+    /// `retrying` tells `request` whether a previous attempt failed
+    pub(crate) fn retry<T, E: Display>(
+        self,
+        what: &str,
+        mut request: impl FnMut(bool) -> Result<T, E>,
+        transient: impl Fn(&E) -> bool,
+    ) -> Result<T, E> {
+        let mut delay = self.delay;
+        let mut attempt = 0;
+        loop {
+            match request(attempt > 0) {
+                Err(error) if attempt < self.retries && transient(&error) => {
+                    attempt += 1;
+                    log::warn!(
+                        "{what} failed: {error}, retrying in {} ms ({attempt}/{})",
+                        delay.as_millis(),
+                        self.retries
+                    );
+                    std::thread::sleep(delay);
+                    delay = delay.saturating_mul(2);
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Options for retrying failed requests to the window manager
+#[derive(clap::Args, Debug)]
+pub struct RetryArgs {
+    /// Retry a failed connection to the window manager up to N times.
+    ///
+    /// Connecting fails right after login or while i3 restarts.
+    /// Queries and event subscriptions failing on a lost connection are retried on a new one,
+    /// commands only if they could not be sent, as they might have run already
+    #[clap(
+        long,
+        env = "I3IW_RETRY",
+        value_name = "N",
+        default_value_t = 0,
+        global = true
+    )]
+    retry: u32,
+
+    /// Milliseconds to wait before the first retry, doubled for each further one
+    #[clap(
+        long,
+        env = "I3IW_RETRY_DELAY",
+        value_name = "MS",
+        default_value_t = 100,
+        global = true
+    )]
+    retry_delay: u64,
+}
+
+impl RetryArgs {
+    /// Policy for the connection to the window manager
+    #[must_use]
+    pub const fn policy(&self) -> RetryPolicy {
+        RetryPolicy::new(self.retry, Duration::from_millis(self.retry_delay))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::RetryPolicy;
+
+    #[test]
+    fn retries_transient_errors() {
+        let policy = RetryPolicy::new(2, Duration::ZERO);
+        let mut attempts = Vec::new();
+        let result: Result<(), &str> = policy.retry(
+            "Test",
+            |retrying| {
+                attempts.push(retrying);
+                Err("lost")
+            },
+            |_| true,
+        );
+        assert_eq!(result, Err("lost"));
+        assert_eq!(attempts, [false, true, true]);
+
+        let mut attempts = 0;
+        let result = policy.retry(
+            "Test",
+            |_| {
+                attempts += 1;
+                if attempts < 2 {
+                    Err("lost")
+                } else {
+                    Ok(attempts)
+                }
+            },
+            |_| true,
+        );
+        assert_eq!(result, Ok(2));
+    }
+
+    #[test]
+    fn stops_on_other_errors() {
+        let mut attempts = 0;
+        let result: Result<(), &str> = RetryPolicy::new(3, Duration::ZERO).retry(
+            "Test",
+            |_| {
+                attempts += 1;
+                Err("rejected")
+            },
+            |error| *error == "lost",
+        );
+        assert_eq!(result, Err("rejected"));
+        assert_eq!(attempts, 1);
+    }
+}
@@ -0,0 +1,97 @@
+//! Script mode for rofi, choosing the insertion point in a rofi menu
+//!
+//! rofi runs the script without an argument to list the entries, and again with the selected entry as argument.
+//! The script mode is started with `rofi -show insert -modi "insert:i3-insert-workspace rofi-mode"`
+use std::io::Write;
+
+use thiserror::Error;
+
+use crate::{
+    connection::Connection,
+    naming::NameSelection,
+    util::{get_workspaces, Pivot},
+};
+
+/// Environment variable in which rofi passes the info of the selected entry
+const INFO_VARIABLE: &str = "ROFI_INFO";
+
+/// Errors of the rofi script mode
+#[derive(Debug, Error)]
+pub enum RofiError {
+    #[error("Could not get workspaces: {0}")]
+    IPCCommunication(#[from] i3ipc::MessageError),
+    #[error("Could not write entries: {0}")]
+    Write(#[from] std::io::Error),
+    #[error("No insertion point in {INFO_VARIABLE}, rofi 1.6 or newer is needed")]
+    MissingInfo,
+    #[error("Malformed insertion point \"{0}\" in {INFO_VARIABLE}")]
+    MalformedInfo(String),
+}
+
+/// Options of the `rofi-mode` subcommand
+#[derive(clap::Args, Debug)]
+pub struct RofiModeArgs {
+    /// Entry selected in rofi, passed by rofi when running the script the second time
+    #[clap(hide = true)]
+    selection: Option<String>,
+
+    /// Naming of the new workspace
+    #[clap(flatten)]
+    name: NameSelection,
+}
+
+impl RofiModeArgs {
+    /// List the insertion points, or return the selected one
+    ///
+    /// Without a selection the entries are printed for rofi, and `None` is returned.
+    /// Otherwise returns the pivot, whether to insert before it, and the naming
+    pub fn run(
+        self,
+        conn: &mut Connection,
+        focused_workspace: &str,
+    ) -> Result<Option<(Pivot, bool, NameSelection)>, RofiError> {
+        if self.selection.is_none() {
+            print_entries(conn, focused_workspace)?;
+            return Ok(None);
+        }
+        let info = std::env::var(INFO_VARIABLE).map_err(|_| RofiError::MissingInfo)?;
+        let mut fields = info.splitn(3, '\t');
+        let (Some(position), Some(output), Some(name)) =
+            (fields.next(), fields.next(), fields.next())
+        else {
+            return Err(RofiError::MalformedInfo(info));
+        };
+        let before = match position {
+            "before" => true,
+            "after" => false,
+            _ => return Err(RofiError::MalformedInfo(info)),
+        };
+        let pivot = Pivot {
+            name: name.to_owned(),
+            output: Some(output.to_owned()),
+        };
+        Ok(Some((pivot, before, self.name)))
+    }
+}
+
+/// Print an entry for inserting before and after each workspace, in rofi's script mode format
+///
+/// The entries of the focused workspace are highlighted as active
+fn print_entries(conn: &mut Connection, focused_workspace: &str) -> Result<(), RofiError> {
+    let workspaces = get_workspaces(conn)?;
+    let mut stdout = std::io::stdout().lock();
+    writeln!(stdout, "\0prompt\x1finsert")?;
+    if let Some(index) = workspaces.iter().position(|x| x.name == focused_workspace) {
+        writeln!(stdout, "\0active\x1f{},{}", 2 * index, 2 * index + 1)?;
+    }
+    for workspace in &workspaces {
+        for position in ["before", "after"] {
+            writeln!(
+                stdout,
+                "{position} {} ({})\0info\x1f{position}\t{}\t{}",
+                workspace.name, workspace.output, workspace.output, workspace.name
+            )?;
+        }
+    }
+    Ok(())
+}
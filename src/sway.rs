@@ -0,0 +1,83 @@
+//! Connection to sway through `swayipc`, used by the sway insertion method
+//!
+//! The tree keeps the sway specific properties like `app_id`, which `i3ipc` drops
+use i3ipc::reply::{CommandOutcome, Workspace};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{connection::log_outcomes, raw_tree::RawNode};
+
+/// Errors of the `swayipc` connection
+#[derive(Debug, Error)]
+pub enum SwayError {
+    #[error("sway IPC error: {0}")]
+    Ipc(#[from] swayipc::Error),
+    #[error("Could not convert tree: {0}")]
+    Tree(#[from] serde_json::Error),
+}
+
+/// Connection to sway
+pub struct SwayConnection {
+    conn: swayipc::Connection,
+}
+
+impl SwayConnection {
+    /// Connect to the sway socket given by `SWAYSOCK`
+    pub fn connect() -> Result<Self, SwayError> {
+        Ok(Self {
+            conn: swayipc::Connection::new()?,
+        })
+    }
+
+    /// Get the workspaces, in the order of their outputs
+    pub fn get_workspaces(&mut self) -> Result<Vec<Workspace>, SwayError> {
+        log::debug!("Requesting sway workspaces");
+        Ok(self
+            .conn
+            .get_workspaces()?
+            .into_iter()
+            .map(|x| Workspace {
+                num: x.num,
+                name: x.name,
+                visible: x.visible,
+                focused: x.focused,
+                urgent: x.urgent,
+                rect: (x.rect.x, x.rect.y, x.rect.width, x.rect.height),
+                output: x.output,
+            })
+            .collect())
+    }
+
+    /// Get the container tree, with all properties sway reports
+    pub fn get_tree(&mut self) -> Result<RawNode, SwayError> {
+        log::debug!("Requesting sway tree");
+        let tree = serde_json::to_value(self.conn.get_tree()?)?;
+        Ok(RawNode::deserialize(tree)?)
+    }
+
+    /// Send `command`, returning the outcome of each command in it
+    pub fn run_command(&mut self, command: &str) -> Result<Vec<CommandOutcome>, SwayError> {
+        log::info!("Running command through swayipc: {command}");
+        let outcomes = self
+            .conn
+            .run_command(command)?
+            .into_iter()
+            .map(|outcome| match outcome {
+                Ok(()) => CommandOutcome {
+                    success: true,
+                    error: None,
+                },
+                Err(swayipc::Error::CommandFailed(error)) => CommandOutcome {
+                    success: false,
+                    error: Some(error),
+                },
+                Err(error) => CommandOutcome {
+                    success: false,
+                    error: Some(error.to_string()),
+                },
+            })
+            .collect::<Vec<_>>();
+        log_outcomes(&outcomes);
+        Ok(outcomes)
+    }
+}
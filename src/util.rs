@@ -1,21 +1,182 @@
+use std::path::Path;
+
+use i3ipc::reply::Workspace;
+use thiserror::Error;
+
+use crate::connection::Connection;
+
+/// Internal output of the window manager, holding the scratchpad workspace
+pub const SCRATCHPAD_OUTPUT: &str = "__i3";
+/// Internal workspace holding the scratchpad windows
+pub const SCRATCHPAD_WORKSPACE: &str = "__i3_scratch";
+
+/// Workspace before or after which the new workspace is inserted
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Pivot {
+    pub name: String,
+    /// Output containing the pivot, to tell apart equally named workspaces on different outputs
+    pub output: Option<String>,
+}
+
+/// Rename of an existing workspace, done together with the insertion
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct WorkspaceRename {
+    pub from: String,
+    pub to: String,
+}
+
+impl WorkspaceRename {
+    /// i3 command performing the rename
+    pub fn command(&self) -> String {
+        format!("rename workspace \"{}\" to \"{}\"", self.from, self.to)
+    }
+}
+
+/// Errors for `run_commands`
+#[derive(Debug, Error)]
+pub enum CommandError {
+    #[error("i3 IPC connection error: \"{0}\"")]
+    Connection(#[from] i3ipc::MessageError),
+    #[error("i3 IPC command error: \"{0}\"")]
+    Failed(String),
+}
+
+/// Point every IPC connection at the same socket, by setting `I3SOCK` and `SWAYSOCK`
+///
+/// `i3ipc`, `swayipc` and the raw tree requests only find the socket through these variables,
+/// none of them connects to a given path. So `path` overrides both, and it has to be set before
+/// the first connection, while no other thread runs.
+/// Without `path`, `SWAYSOCK` is used for `I3SOCK` if only it is set
+pub fn select_socket(path: Option<&Path>) {
+    if let Some(path) = path {
+        log::debug!("Using socket {}", path.display());
+        std::env::set_var("I3SOCK", path);
+        std::env::set_var("SWAYSOCK", path);
+    } else if std::env::var_os("I3SOCK").is_none() {
+        if let Some(path) = std::env::var_os("SWAYSOCK") {
+            std::env::set_var("I3SOCK", path);
+        }
+    }
+}
+
+/// Get the workspaces, in the order of their outputs
+pub fn get_workspaces(conn: &mut Connection) -> Result<Vec<Workspace>, i3ipc::MessageError> {
+    log::debug!("Requesting workspaces");
+    let workspaces = conn.get_workspaces()?;
+    log::trace!(
+        "Workspaces: {}",
+        workspaces
+            .iter()
+            .map(|x| format!("\"{}\" on {}", x.name, x.output))
+            .collect::<Vec<_>>()
+            .join(", ")
+    );
+    Ok(workspaces)
+}
+
+/// Run `commands` in a single message, failing if any of them failed
+pub fn run_commands(conn: &mut Connection, commands: &[String]) -> Result<(), CommandError> {
+    if commands.is_empty() {
+        return Ok(());
+    }
+    conn.run_command(&commands.join("; "))?
+        .into_iter()
+        .find(|x| !x.success)
+        .map_or(Ok(()), |x| {
+            Err(CommandError::Failed(x.error.unwrap_or_else(|| {
+                "No error message, but errored".to_owned()
+            })))
+        })
+}
+
+/// Order `renames` so that no rename targets a name still in use by another renamed workspace
+///
+/// Cyclic renames are broken up by renaming one workspace to a temporary name first.
+/// Renames to the same name are dropped.
+pub fn collision_free(renames: Vec<WorkspaceRename>) -> Vec<WorkspaceRename> {
+    let mut pending = renames
+        .into_iter()
+        .filter(|x| x.from != x.to)
+        .collect::<Vec<_>>();
+    let mut ordered = Vec::new();
+    let mut temporary_count = 0;
+    while !pending.is_empty() {
+        let free = pending
+            .iter()
+            .position(|x| !pending.iter().any(|other| other.from == x.to));
+        if let Some(index) = free {
+            ordered.push(pending.remove(index));
+        } else if let Some(first) = pending.first_mut() {
+            let temporary = format!("i3-insert-workspace-temporary-{temporary_count}");
+            temporary_count += 1;
+            ordered.push(WorkspaceRename {
+                from: std::mem::replace(&mut first.from, temporary.clone()),
+                to: temporary,
+            });
+        }
+    }
+    ordered
+}
+
+/// Get the name of the workspace `name` after applying `renames`
+pub fn renamed<'a>(renames: &'a [WorkspaceRename], name: &'a str) -> &'a str {
+    renames
+        .iter()
+        .find(|x| x.from == name)
+        .map_or(name, |x| x.to.as_str())
+}
+
+/// Changes made to the moved containers by the insertion, right before moving them
+#[derive(Clone, Copy, Default, Debug)]
+pub struct ContainerChanges<'a> {
+    /// Mark added to the first moved container
+    pub mark: Option<&'a str>,
+    /// Sticky containers made non-sticky, which would otherwise stay shown on every workspace
+    pub unstick: &'a [i64],
+}
+
+impl ContainerChanges<'_> {
+    /// Commands making the changes, marking the first of `containers`
+    pub fn commands(&self, containers: &[i64]) -> Vec<String> {
+        self.unstick
+            .iter()
+            .map(|x| format!("[con_id={x}] sticky disable"))
+            .chain(
+                containers
+                    .first()
+                    .zip(self.mark)
+                    .map(|(container, mark)| format!("[con_id={container}] mark --add \"{mark}\"")),
+            )
+            .collect()
+    }
+}
+
 /// Insert workspace before or after pivot
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum InsertionDestination {
-    After { pivot: String },
-    Before { pivot: String },
+    After { pivot: Pivot },
+    Before { pivot: Pivot },
 }
 
 impl InsertionDestination {
-    pub const fn new(pivot: String, before: bool) -> Self {
+    #[must_use]
+    pub const fn new(pivot: Pivot, before: bool) -> Self {
         if before {
             Self::Before { pivot }
         } else {
             Self::After { pivot }
         }
     }
+    #[must_use]
     pub fn pivot(&self) -> &str {
         match &self {
-            Self::After { pivot } | Self::Before { pivot } => pivot,
+            Self::After { pivot } | Self::Before { pivot } => &pivot.name,
+        }
+    }
+    #[must_use]
+    pub fn pivot_output(&self) -> Option<&str> {
+        match &self {
+            Self::After { pivot } | Self::Before { pivot } => pivot.output.as_deref(),
         }
     }
 }